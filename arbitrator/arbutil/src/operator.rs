@@ -12,6 +12,36 @@ pub struct OperatorCode(usize);
 impl OperatorCode {
     // TODO: use std::mem::variant_count when it's stabilized
     pub const OPERATOR_COUNT: usize = 529;
+
+    /// Groups the operator by what it does, going by its opcode.
+    pub fn category(self) -> OperatorCategory {
+        use OperatorCategory::*;
+        match self.0 {
+            0x10..=0x13 => Call,
+            0x00..=0x19 => ControlFlow,
+            0x28..=0x40 | 0xfc08..=0xfc0b | 0xfe00..=0xfe4e => Memory,
+            0x41..=0xc4 | 0xfc00..=0xfc07 => Arithmetic,
+            0xfd00..=0xfdff | 0xfd111..=0xfd114 => Simd,
+            _ => Other,
+        }
+    }
+}
+
+/// A coarse grouping of operators, so that they may be priced en masse.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum OperatorCategory {
+    /// Loads, stores, atomics, and bulk memory operations
+    Memory,
+    /// Constants, comparisons, numeric operations, and conversions
+    Arithmetic,
+    /// Blocks, branches, returns, and exception handling
+    ControlFlow,
+    /// Direct and indirect calls
+    Call,
+    /// Vector operations, including vector loads and stores
+    Simd,
+    /// Everything else, like variable, table, and reference operations
+    Other,
 }
 
 impl Display for OperatorCode {
@@ -1106,6 +1136,7 @@ impl<'a> From<&Operator<'a>> for OperatorCode {
 
 pub trait OperatorInfo {
     fn ends_basic_block(&self) -> bool;
+    fn is_conversion(&self) -> bool;
     fn code(&self) -> OperatorCode;
 }
 
@@ -1125,6 +1156,24 @@ impl OperatorInfo for Operator<'_> {
         )
     }
 
+    /// Whether the operator converts between an integer and a float.
+    fn is_conversion(&self) -> bool {
+        use Operator::*;
+
+        #[rustfmt::skip]
+        let conversion = matches!(
+            self,
+            I32TruncF32S | I32TruncF32U | I32TruncF64S | I32TruncF64U |
+            I64TruncF32S | I64TruncF32U | I64TruncF64S | I64TruncF64U |
+            I32TruncSatF32S | I32TruncSatF32U | I32TruncSatF64S | I32TruncSatF64U |
+            I64TruncSatF32S | I64TruncSatF32U | I64TruncSatF64S | I64TruncSatF64U |
+            F32ConvertI32S | F32ConvertI32U | F32ConvertI64S | F32ConvertI64U |
+            F64ConvertI32S | F64ConvertI32U | F64ConvertI64S | F64ConvertI64U |
+            I32ReinterpretF32 | I64ReinterpretF64 | F32ReinterpretI32 | F64ReinterpretI64
+        );
+        conversion
+    }
+
     fn code(&self) -> OperatorCode {
         self.into()
     }
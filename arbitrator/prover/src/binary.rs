@@ -3,9 +3,22 @@
 
 use crate::{
     programs::{
-        config::CompileConfig, counter::Counter, depth::DepthChecker, dynamic::DynamicMeter,
-        heap::HeapBound, meter::Meter, start::StartMover, FuncMiddleware, Middleware, ModuleMod,
-        StylusData, STYLUS_ENTRY_POINT,
+        align::AlignmentCheck,
+        config::{AllowedProposals, CompileConfig},
+        constant::ConstBound,
+        counter::Counter,
+        depth::DepthChecker,
+        dynamic::DynamicMeter,
+        elements::PassiveElementBound,
+        features::FeatureCheck,
+        heap::HeapBound,
+        limits::ModuleLimits,
+        locals::LocalsBound,
+        meter::Meter,
+        nonfloat::NonFloat,
+        start::StartMover,
+        watchdog::StepWatchdog,
+        FuncMiddleware, Middleware, ModuleMod, StylusData, STYLUS_ENTRY_POINT,
     },
     value::{ArbValueType, FunctionType, IntegerValType, Value},
 };
@@ -19,12 +32,15 @@ use nom::{
     sequence::{preceded, tuple},
 };
 use serde::{Deserialize, Serialize};
-use std::{convert::TryInto, fmt::Debug, hash::Hash, mem, path::Path, str::FromStr};
+use std::{
+    collections::BTreeMap, convert::TryInto, fmt::Debug, hash::Hash, mem, ops::Range, path::Path,
+    str::FromStr,
+};
 use wasmer_types::{entity::EntityRef, FunctionIndex, LocalFunctionIndex};
 use wasmparser::{
-    Data, Element, Export, ExternalKind, Global, Import, MemoryType, Name, NameSectionReader,
-    Naming, Operator, Parser, Payload, SectionReader, SectionWithLimitedItems, TableType, Type,
-    TypeRef, ValType, Validator, WasmFeatures,
+    ConstExpr, Data, Element, ElementItem, ElementKind, Export, ExternalKind, Global, Import,
+    MemoryType, Name, NameSectionReader, Naming, Operator, Parser, Payload, SectionReader,
+    SectionWithLimitedItems, TableType, Type, TypeRef, ValType, Validator, WasmFeatures,
 };
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -254,6 +270,44 @@ pub struct Code<'a> {
     pub expr: Vec<Operator<'a>>,
 }
 
+/// An element segment with its items read out, so that rewrites may renumber them.
+#[derive(Clone)]
+pub struct ElementSegment<'a> {
+    pub kind: ElementKind<'a>,
+    pub ty: ValType,
+    pub items: Vec<ElementInit<'a>>,
+    /// Where the segment lies in the original wasm
+    pub range: Range<usize>,
+}
+
+impl<'a> TryFrom<Element<'a>> for ElementSegment<'a> {
+    type Error = eyre::Error;
+
+    fn try_from(elem: Element<'a>) -> Result<Self> {
+        let mut reader = elem.items.get_items_reader()?;
+        let mut items = Vec::with_capacity(reader.get_count() as usize);
+        for _ in 0..reader.get_count() {
+            items.push(match reader.read()? {
+                ElementItem::Func(func) => ElementInit::Func(func),
+                ElementItem::Expr(expr) => ElementInit::Expr(expr),
+            });
+        }
+        Ok(Self {
+            kind: elem.kind,
+            ty: elem.ty,
+            items,
+            range: elem.range,
+        })
+    }
+}
+
+/// An element segment item, which is either a function or a constant expression.
+#[derive(Clone)]
+pub enum ElementInit<'a> {
+    Func(u32),
+    Expr(ConstExpr<'a>),
+}
+
 #[derive(Clone, Debug)]
 pub struct Local {
     pub index: u32,
@@ -266,7 +320,8 @@ pub struct NameCustomSection {
     pub functions: HashMap<u32, String>,
 }
 
-pub type ExportMap = HashMap<String, (u32, ExportKind)>;
+/// Exports keyed by name, ordered so that iteration doesn't depend on how the module listed them.
+pub type ExportMap = BTreeMap<String, (u32, ExportKind)>;
 
 #[derive(Clone, Default)]
 pub struct WasmBinary<'a> {
@@ -279,10 +334,13 @@ pub struct WasmBinary<'a> {
     pub globals: Vec<Value>,
     pub exports: ExportMap,
     pub start: Option<u32>,
-    pub elements: Vec<Element<'a>>,
+    pub elements: Vec<ElementSegment<'a>>,
     pub codes: Vec<Code<'a>>,
     pub datas: Vec<Data<'a>>,
     pub names: NameCustomSection,
+    /// Every custom section's name and payload in binary order, including the name section.
+    /// All are retained when parsing, so stripping is left to the caller.
+    pub customs: Vec<(&'a str, &'a [u8])>,
 }
 
 pub fn parse<'a>(input: &'a [u8], path: &'_ Path) -> Result<WasmBinary<'a>> {
@@ -410,10 +468,15 @@ pub fn parse<'a>(input: &'a [u8], path: &'_ Path) -> Result<WasmBinary<'a>> {
             TableSection(tables) => process!(binary.tables, tables),
             MemorySection(memories) => process!(binary.memories, memories),
             StartSection { func, .. } => binary.start = Some(*func),
-            ElementSection(elements) => process!(binary.elements, elements),
+            ElementSection(elements) => {
+                for elem in flatten!(Element, elements) {
+                    binary.elements.push(elem.try_into()?);
+                }
+            }
             DataSection(datas) => process!(binary.datas, datas),
             CodeSectionStart { .. } => {}
             CustomSection(reader) => {
+                binary.customs.push((reader.name(), reader.data()));
                 if reader.name() != "name" {
                     continue;
                 }
@@ -490,6 +553,7 @@ pub fn parse<'a>(input: &'a [u8], path: &'_ Path) -> Result<WasmBinary<'a>> {
 
 impl<'a> Debug for WasmBinary<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let customs: Vec<_> = self.customs.iter().map(|x| x.0).collect();
         f.debug_struct("WasmBinary")
             .field("types", &self.types)
             .field("imports", &self.imports)
@@ -503,6 +567,7 @@ impl<'a> Debug for WasmBinary<'a> {
             .field("codes", &self.codes)
             .field("datas", &self.datas)
             .field("names", &self.names)
+            .field("customs", &customs)
             .finish()
     }
 }
@@ -510,18 +575,57 @@ impl<'a> Debug for WasmBinary<'a> {
 impl<'a> WasmBinary<'a> {
     /// Instruments a user wasm, producing a version bounded via configurable instrumentation.
     pub fn instrument(&mut self, compile: &CompileConfig) -> Result<StylusData> {
+        self.instrument_with_metrics(compile, &mut |_, _| {})
+    }
+
+    /// Instruments a user wasm like [`WasmBinary::instrument`], reporting each middleware's
+    /// metrics to the given sink.
+    pub fn instrument_with_metrics(
+        &mut self,
+        compile: &CompileConfig,
+        sink: &mut dyn FnMut(&str, u64),
+    ) -> Result<StylusData> {
+        let limits = ModuleLimits::new(compile.bounds);
         let meter = Meter::new(&compile.pricing);
         let dygas = DynamicMeter::new(&compile.pricing);
         let depth = DepthChecker::new(compile.bounds);
         let bound = HeapBound::new(compile.bounds);
         let start = StartMover::default();
+        let align = AlignmentCheck::new(compile.bounds);
+        let consts = ConstBound::new(compile.bounds);
 
+        limits.update_module(self)?; // precedes any added globals or exports
+        consts.update_module(self)?;
         meter.update_module(self)?;
         dygas.update_module(self)?;
         depth.update_module(self)?;
         bound.update_module(self)?;
         start.update_module(self)?;
+        align.update_module(self)?;
 
+        let elements = compile.bounds.max_passive_elements;
+        if let Some(elements) = elements.map(PassiveElementBound::new) {
+            elements.update_module(self)?; // only checks the module
+        }
+        let nonfloat = compile.bounds.forbid_floats.then_some(NonFloat);
+        if let Some(nonfloat) = &nonfloat {
+            nonfloat.update_module(self)?;
+        }
+        let proposals = compile.bounds.proposals;
+        let features =
+            (proposals != AllowedProposals::default()).then(|| FeatureCheck::new(proposals));
+        if let Some(features) = &features {
+            features.update_module(self)?;
+        }
+        let max_locals = compile.bounds.max_locals_per_function;
+        let locals_bound = max_locals.map(LocalsBound::new);
+        if let Some(locals_bound) = &locals_bound {
+            locals_bound.update_module(self)?;
+        }
+        let watchdog = compile.debug.max_steps.map(StepWatchdog::new);
+        if let Some(watchdog) = &watchdog {
+            watchdog.update_module(self)?;
+        }
         let count = compile.debug.count_ops.then(Counter::new);
         if let Some(count) = &count {
             count.update_module(self)?;
@@ -546,17 +650,33 @@ impl<'a> WasmBinary<'a> {
                         mid.feed(op, &mut build)
                             .wrap_err_with(|| format!("{} failure", mid.name()))?
                     }
+                    mid.finish()
+                        .wrap_err_with(|| format!("{} failure", mid.name()))?;
                 };
             }
 
             // add the instrumentation in the order of application
             // note: this must be consistent with native execution
+            apply!(consts); // precedes the meter's own constants
             apply!(meter);
             apply!(dygas);
             apply!(depth);
             apply!(bound);
             apply!(start);
+            apply!(align);
 
+            if let Some(nonfloat) = &nonfloat {
+                apply!(*nonfloat);
+            }
+            if let Some(features) = &features {
+                apply!(*features);
+            }
+            if let Some(locals_bound) = &locals_bound {
+                apply!(*locals_bound);
+            }
+            if let Some(watchdog) = &watchdog {
+                apply!(*watchdog);
+            }
             if let Some(count) = &count {
                 apply!(*count);
             }
@@ -564,6 +684,9 @@ impl<'a> WasmBinary<'a> {
             code.expr = build;
         }
 
+        Middleware::<WasmBinary>::metrics(&meter, sink);
+        Middleware::<WasmBinary>::metrics(&bound, sink);
+
         // 4GB maximum implies `footprint` fits in a u16
         let footprint = self.memory_info()?.min.0 as u16;
 
@@ -590,6 +713,77 @@ impl<'a> WasmBinary<'a> {
         })
     }
 
+    /// Applies a single middleware to the module and then to each of its functions.
+    pub fn apply_middleware<M>(&mut self, middleware: &M) -> Result<()>
+    where
+        M: Middleware<WasmBinary<'a>>,
+    {
+        middleware.update_module(self)?;
+
+        for (index, code) in self.codes.iter_mut().enumerate() {
+            let index = LocalFunctionIndex::from_u32(index as u32);
+            let locals: Vec<ValType> = code.locals.iter().map(|x| x.value.into()).collect();
+
+            let mut mid = middleware.instrument(index)?;
+            mid.locals_info(&locals);
+
+            let mut build = Vec::with_capacity(code.expr.len());
+            for op in mem::take(&mut code.expr) {
+                mid.feed(op, &mut build)
+                    .wrap_err_with(|| format!("{} failure", mid.name()))?
+            }
+            mid.finish()
+                .wrap_err_with(|| format!("{} failure", mid.name()))?;
+            code.expr = build;
+        }
+        Ok(())
+    }
+
+    /// Gets the initial value of the global exported under the given name.
+    pub fn global_value(&self, name: &str) -> Result<Value> {
+        let index = self.exported_global(name)?;
+        Ok(self.globals[index])
+    }
+
+    /// Overwrites the initial value of the global exported under the given name.
+    /// The new value must have the same type as the one it replaces.
+    pub fn set_global_value(&mut self, name: &str, value: Value) -> Result<()> {
+        let index = self.exported_global(name)?;
+        let global = &mut self.globals[index];
+        if global.ty() != value.ty() {
+            let (ty, new) = (global.ty().red(), value.ty().red());
+            bail!("cannot set global {} of type {ty} to a {new}", name.red())
+        }
+        *global = value;
+        Ok(())
+    }
+
+    /// Gets the payload of the first custom section with the given name, if one exists.
+    pub fn custom_section(&self, name: &str) -> Option<&'a [u8]> {
+        let section = self.customs.iter().find(|x| x.0 == name);
+        section.map(|x| x.1)
+    }
+
+    /// Drops every custom section whose name isn't in `keep`, such as debug info.
+    /// Since the name section was already parsed, `names` is untouched either way.
+    pub fn strip_custom_sections(&mut self, keep: &[&str]) {
+        self.customs.retain(|(name, _)| keep.contains(name));
+    }
+
+    fn exported_global(&self, name: &str) -> Result<usize> {
+        let Some(&(index, kind)) = self.exports.get(name) else {
+            bail!("global {} not found", name.red())
+        };
+        if kind != ExportKind::Global {
+            bail!("export {} is not a global", name.red())
+        }
+        let index = index as usize;
+        if index >= self.globals.len() {
+            bail!("missing global {} @ index {}", name.red(), index.red())
+        }
+        Ok(index)
+    }
+
     /// Parses and instruments a user wasm
     pub fn parse_user(
         wasm: &'a [u8],
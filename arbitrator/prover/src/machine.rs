@@ -3,7 +3,8 @@
 
 use crate::{
     binary::{
-        self, parse, ExportKind, ExportMap, FloatInstruction, Local, NameCustomSection, WasmBinary,
+        self, parse, ElementInit, ExportKind, ExportMap, FloatInstruction, Local,
+        NameCustomSection, WasmBinary,
     },
     host,
     memory::Memory,
@@ -41,7 +42,7 @@ use std::{
     sync::Arc,
 };
 use wasmer_types::FunctionIndex;
-use wasmparser::{DataKind, ElementItem, ElementKind, Operator, TableType};
+use wasmparser::{DataKind, ElementKind, Operator, TableType};
 
 #[cfg(feature = "rayon")]
 use rayon::prelude::*;
@@ -511,10 +512,8 @@ impl Module {
             );
 
             let mut contents = vec![];
-            let mut item_reader = elem.items.get_items_reader()?;
-            for _ in 0..item_reader.get_count() {
-                let item = item_reader.read()?;
-                let ElementItem::Func(index) = item else {
+            for item in &elem.items {
+                let ElementInit::Func(index) = *item else {
                     bail!("Non-constant element initializers are not supported")
                 };
                 let func_ty = func_types[index as usize].clone();
@@ -1348,7 +1347,7 @@ impl Machine {
             start_function: None,
             func_types: Arc::new(vec![FunctionType::default()]),
             func_exports: Arc::new(HashMap::default()),
-            all_exports: Arc::new(HashMap::default()),
+            all_exports: Arc::new(ExportMap::default()),
         };
         modules[0] = entrypoint;
 
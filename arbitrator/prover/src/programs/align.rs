@@ -0,0 +1,93 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{config::CompileMemoryParams, FuncMiddleware, Middleware, ModuleMod};
+use arbutil::{operator::OperatorInfo, Color};
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Optionally requires memory accesses to be naturally aligned, since misaligned ones,
+/// while valid, are expensive to prove.
+#[derive(Debug)]
+pub struct AlignmentCheck {
+    /// Whether to reject under-aligned accesses
+    strict: bool,
+}
+
+impl AlignmentCheck {
+    pub fn new(bounds: CompileMemoryParams) -> Self {
+        Self {
+            strict: bounds.strict_alignment,
+        }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for AlignmentCheck {
+    type FM<'a> = FuncAlignmentCheck;
+
+    fn update_module(&self, _: &mut M) -> Result<()> {
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncAlignmentCheck {
+            strict: self.strict,
+            func,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "alignment check"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncAlignmentCheck {
+    /// Whether to reject under-aligned accesses
+    strict: bool,
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncAlignmentCheck {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        if self.strict {
+            #[rustfmt::skip]
+            let memarg = match &op {
+                I32Load { memarg } | I64Load { memarg } | F32Load { memarg } | F64Load { memarg } |
+                I32Load8S { memarg } | I32Load8U { memarg } | I32Load16S { memarg } |
+                I32Load16U { memarg } | I64Load8S { memarg } | I64Load8U { memarg } |
+                I64Load16S { memarg } | I64Load16U { memarg } | I64Load32S { memarg } |
+                I64Load32U { memarg } | I32Store { memarg } | I64Store { memarg } |
+                F32Store { memarg } | F64Store { memarg } | I32Store8 { memarg } |
+                I32Store16 { memarg } | I64Store8 { memarg } | I64Store16 { memarg } |
+                I64Store32 { memarg } => Some(memarg),
+                _ => None,
+            };
+
+            // alignments are expressed as powers of 2
+            if let Some(memarg) = memarg.filter(|x| x.align < x.max_align) {
+                let func = self.func.as_u32().red();
+                let align = 1 << memarg.align;
+                let natural = 1 << memarg.max_align;
+                bail!(
+                    "func {func} has {} aligned to {} bytes instead of {natural}",
+                    op.code().red(),
+                    align.red(),
+                )
+            }
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "alignment check"
+    }
+}
@@ -0,0 +1,502 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{depth, ModuleMod};
+use crate::binary::{ElementInit, ExportKind, WasmBinary};
+use arbutil::{
+    operator::{OperatorCategory, OperatorInfo},
+    Color,
+};
+use eyre::{bail, Result};
+use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
+use wasmer_types::{FunctionIndex, LocalFunctionIndex, WASM_MAX_PAGES, WASM_PAGE_SIZE};
+use wasmparser::{DataKind, ElementKind, Operator};
+
+/// The assumed size of an opcode once compiled, used when estimating footprints.
+pub const ESTIMATED_BYTES_PER_OP: u64 = 16;
+
+impl<'a> WasmBinary<'a> {
+    /// Gets the functions stored in element segments, in order of appearance.
+    pub fn element_funcs(&self) -> Result<Vec<u32>> {
+        let mut funcs = vec![];
+        for elem in &self.elements {
+            for item in &elem.items {
+                if let ElementInit::Func(index) = item {
+                    funcs.push(*index);
+                }
+            }
+        }
+        Ok(funcs)
+    }
+
+    /// Gets the function each slot of a table is initialized to by active element segments,
+    /// with `None` for null or unset slots.
+    pub fn table_initializers(&self, table: u32) -> Result<Vec<Option<FunctionIndex>>> {
+        let Some(ty) = self.tables.get(table as usize) else {
+            bail!("missing table {}", table.red())
+        };
+        let mut slots = vec![None; ty.initial as usize];
+
+        for elem in &self.elements {
+            let ElementKind::Active {
+                table_index,
+                offset_expr,
+            } = &elem.kind
+            else {
+                continue;
+            };
+            if *table_index != table {
+                continue;
+            }
+            let mut init = offset_expr.get_operators_reader();
+            let offset = match (init.read()?, init.read()?, init.eof()) {
+                (Operator::I32Const { value }, Operator::End, true) => value as u32 as usize,
+                x => bail!("non-constant element segment offset {:?}", x),
+            };
+
+            let count = elem.items.len();
+            if offset.saturating_add(count) > slots.len() {
+                let table = table.red();
+                bail!(
+                    "element segment at offset {} overflows table {table}",
+                    offset.red()
+                );
+            }
+            for (slot, item) in slots[offset..][..count].iter_mut().zip(&elem.items) {
+                *slot = match item {
+                    ElementInit::Func(func) => Some(FunctionIndex::from_u32(*func)),
+                    ElementInit::Expr(expr) => match expr.get_operators_reader().read()? {
+                        Operator::RefFunc { function_index } => {
+                            Some(FunctionIndex::from_u32(function_index))
+                        }
+                        Operator::RefNull { .. } => None,
+                        x => bail!("non-constant element initializer {:?}", x),
+                    },
+                };
+            }
+        }
+        Ok(slots)
+    }
+
+    /// Finds the pairs of active data segments that write to overlapping bytes of the same memory,
+    /// each pair given in ascending order of segment index.
+    pub fn data_overlaps(&self) -> Result<Vec<(usize, usize)>> {
+        let mut ranges = vec![];
+        for (index, data) in self.datas.iter().enumerate() {
+            let DataKind::Active {
+                memory_index,
+                offset_expr,
+            } = &data.kind
+            else {
+                continue;
+            };
+            let mut init = offset_expr.get_operators_reader();
+            let offset = match (init.read()?, init.read()?, init.eof()) {
+                (Operator::I32Const { value }, Operator::End, true) => value as u32 as usize,
+                x => bail!("non-constant data segment offset {:?}", x),
+            };
+            if !data.data.is_empty() {
+                let end = offset.saturating_add(data.data.len());
+                ranges.push((index, *memory_index, offset..end));
+            }
+        }
+
+        let mut overlaps = vec![];
+        for (i, (first, memory, range)) in ranges.iter().enumerate() {
+            for (second, other, within) in &ranges[i + 1..] {
+                if memory == other && range.start < within.end && within.start < range.end {
+                    overlaps.push((*first, *second));
+                }
+            }
+        }
+        Ok(overlaps)
+    }
+
+    /// Roughly estimates the host memory the compiled module consumes, combining its code,
+    /// data segments, and the most linear memory it may grow to. Compiled code size depends on
+    /// the compiler, so each opcode is assumed to take [`ESTIMATED_BYTES_PER_OP`] bytes.
+    pub fn estimated_footprint(&self) -> u64 {
+        let ops: u64 = self.codes.iter().map(|x| x.expr.len() as u64).sum();
+        let code = ops.saturating_mul(ESTIMATED_BYTES_PER_OP);
+        let data: u64 = self.datas.iter().map(|x| x.data.len() as u64).sum();
+
+        let max_pages = WASM_MAX_PAGES as u64;
+        let memory: u64 = self
+            .memories
+            .iter()
+            .map(|x| x.maximum.unwrap_or(max_pages).min(max_pages) * WASM_PAGE_SIZE as u64)
+            .sum();
+
+        code.saturating_add(data).saturating_add(memory)
+    }
+
+    /// Whether two modules share the same types, imports, function signatures, globals, memories,
+    /// tables, and exports. Custom sections like names are ignored, as are function bodies.
+    pub fn structurally_eq(&self, other: &WasmBinary) -> bool {
+        let imports = |bin: &WasmBinary| -> Vec<_> {
+            let imports = bin.imports.iter();
+            imports
+                .map(|x| (x.module.to_owned(), x.name.to_owned(), x.offset))
+                .collect()
+        };
+        self.types == other.types
+            && imports(self) == imports(other)
+            && self.functions == other.functions
+            && self.globals == other.globals
+            && self.memories == other.memories
+            && self.tables == other.tables
+            && self.exports == other.exports
+    }
+
+    /// Gets the imports the module uses, whether through a `call` or by otherwise referencing them
+    /// via `ref.func`, an element segment, an export, or the start function.
+    pub fn used_imports(&self) -> Result<HashSet<FunctionIndex>> {
+        let imports = self.imports.len() as u32;
+        let funcs = imports + self.codes.len() as u32;
+        let mut used = HashSet::default();
+
+        let mut mark = |func: u32| -> Result<()> {
+            if func >= funcs {
+                bail!("reference to missing func @ index {}", func.red());
+            }
+            if func < imports {
+                used.insert(FunctionIndex::from_u32(func));
+            }
+            Ok(())
+        };
+
+        for code in &self.codes {
+            for op in &code.expr {
+                if let Operator::Call { function_index } | Operator::RefFunc { function_index } = op
+                {
+                    mark(*function_index)?;
+                }
+            }
+        }
+        for func in self.element_funcs()? {
+            mark(func)?;
+        }
+        for &(index, kind) in self.exports.values() {
+            if kind == ExportKind::Func {
+                mark(index)?;
+            }
+        }
+        if let Some(start) = self.start {
+            mark(start)?;
+        }
+        Ok(used)
+    }
+
+    /// Whether a local function makes no calls, direct or indirect.
+    pub fn is_leaf_function(&self, func: LocalFunctionIndex) -> Result<bool> {
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+        use Operator::*;
+        let call = |op: &Operator| matches!(op, Call { .. } | CallIndirect { .. });
+        Ok(!code.expr.iter().any(call))
+    }
+
+    /// Whether any function accesses linear memory, whether through loads, stores, or the
+    /// size, grow, and bulk-memory operators. Data segments alone don't count.
+    pub fn uses_memory(&self) -> bool {
+        use Operator::*;
+
+        macro_rules! dot {
+            ($first:ident $(,$opcode:ident)* $(,)?) => {
+                $first { .. } $(| $opcode { .. })*
+            };
+        }
+
+        #[rustfmt::skip]
+        let memory = |op: &Operator| matches!(op, dot!(
+            I32Load, I64Load, F32Load, F64Load, I32Load8S, I32Load8U, I32Load16S, I32Load16U,
+            I64Load8S, I64Load8U, I64Load16S, I64Load16U, I64Load32S, I64Load32U, I32Store,
+            I64Store, F32Store, F64Store, I32Store8, I32Store16, I64Store8, I64Store16, I64Store32,
+            MemorySize, MemoryGrow, MemoryFill, MemoryCopy, MemoryInit, DataDrop,
+        ));
+        self.codes.iter().flat_map(|x| &x.expr).any(memory)
+    }
+
+    /// Counts the control structures in a local function, namely its blocks, loops, and ifs.
+    pub fn block_count(&self, func: LocalFunctionIndex) -> Result<u32> {
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+        use Operator::*;
+        let block = |op: &&Operator| matches!(op, Block { .. } | Loop { .. } | If { .. });
+        Ok(code.expr.iter().filter(block).count() as u32)
+    }
+
+    /// Computes the fraction of a local function's operators that are blocks, loops, ifs, elses,
+    /// or branches. Every operator counts toward the total, including the function's final `end`.
+    pub fn control_flow_ratio(&self, func: LocalFunctionIndex) -> Result<f64> {
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+        use Operator::*;
+
+        #[rustfmt::skip]
+        let control = |op: &&Operator| matches!(
+            op,
+            Block { .. } | Loop { .. } | If { .. } | Else | Br { .. } | BrIf { .. } | BrTable { .. }
+        );
+        let total = code.expr.len();
+        let control = code.expr.iter().filter(control).count();
+        Ok(control as f64 / total as f64)
+    }
+
+    /// Gets the highest local index a local function accesses, counting params as locals.
+    /// Functions that don't access any locals yield 0.
+    pub fn max_local_index(&self, func: LocalFunctionIndex) -> Result<u32> {
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+        use Operator::*;
+        let locals = code.expr.iter().filter_map(|op| match op {
+            LocalGet { local_index } | LocalSet { local_index } | LocalTee { local_index } => {
+                Some(*local_index)
+            }
+            _ => None,
+        });
+        Ok(locals.max().unwrap_or_default())
+    }
+
+    /// Lists the operators in a local function that may trap, along with their offsets and why.
+    pub fn trap_sites(&self, func: LocalFunctionIndex) -> Result<Vec<(usize, &'static str)>> {
+        use Operator::*;
+
+        macro_rules! dot {
+            ($first:ident $(,$opcode:ident)* $(,)?) => {
+                $first { .. } $(| $opcode { .. })*
+            };
+        }
+
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+
+        let mut sites = vec![];
+        for (offset, op) in code.expr.iter().enumerate() {
+            #[rustfmt::skip]
+            let reason = match op {
+                Unreachable => "unreachable",
+                I32DivS | I32DivU | I32RemS | I32RemU |
+                I64DivS | I64DivU | I64RemS | I64RemU => "division",
+                I32TruncF32S | I32TruncF32U | I32TruncF64S | I32TruncF64U |
+                I64TruncF32S | I64TruncF32U | I64TruncF64S | I64TruncF64U => "float truncation",
+                dot!(
+                    I32Load, I64Load, F32Load, F64Load, I32Load8S, I32Load8U, I32Load16S,
+                    I32Load16U, I64Load8S, I64Load8U, I64Load16S, I64Load16U, I64Load32S,
+                    I64Load32U, I32Store, I64Store, F32Store, F64Store, I32Store8, I32Store16,
+                    I64Store8, I64Store16, I64Store32, MemoryFill, MemoryCopy, MemoryInit,
+                ) => "memory access",
+                dot!(CallIndirect) => "indirect call",
+                _ => continue,
+            };
+            sites.push((offset, reason));
+        }
+        Ok(sites)
+    }
+
+    /// Counts the operators in a local function by category, keyed by `arithmetic`, `memory`,
+    /// `control`, `call`, `const`, `conversion`, `simd`, or `other`. Conversions are those between
+    /// ints and floats, as in [`OperatorInfo::is_conversion`]. Absent categories are omitted.
+    pub fn operator_categories(
+        &self,
+        func: LocalFunctionIndex,
+    ) -> Result<HashMap<&'static str, usize>> {
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+        use Operator::*;
+
+        let mut counts = HashMap::default();
+        for op in &code.expr {
+            let category = match op {
+                I32Const { .. } | I64Const { .. } | F32Const { .. } | F64Const { .. } => "const",
+                op if op.is_conversion() => "conversion",
+                op => match op.code().category() {
+                    OperatorCategory::Arithmetic => "arithmetic",
+                    OperatorCategory::Memory => "memory",
+                    OperatorCategory::ControlFlow => "control",
+                    OperatorCategory::Call => "call",
+                    OperatorCategory::Simd => "simd",
+                    OperatorCategory::Other => "other",
+                },
+            };
+            *counts.entry(category).or_default() += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Lists the static offset and width in bytes of each load and store in a local function,
+    /// in program order. Bulk-memory operators aren't included since their extents are dynamic.
+    pub fn memory_accesses(&self, func: LocalFunctionIndex) -> Result<Vec<(u64, u32)>> {
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+        use Operator::*;
+
+        let accesses = code.expr.iter().filter_map(|op| {
+            #[rustfmt::skip]
+            let memarg = match op {
+                I32Load { memarg } | I64Load { memarg } | F32Load { memarg } | F64Load { memarg } |
+                I32Load8S { memarg } | I32Load8U { memarg } | I32Load16S { memarg } |
+                I32Load16U { memarg } | I64Load8S { memarg } | I64Load8U { memarg } |
+                I64Load16S { memarg } | I64Load16U { memarg } | I64Load32S { memarg } |
+                I64Load32U { memarg } | I32Store { memarg } | I64Store { memarg } |
+                F32Store { memarg } | F64Store { memarg } | I32Store8 { memarg } |
+                I32Store16 { memarg } | I64Store8 { memarg } | I64Store16 { memarg } |
+                I64Store32 { memarg } => memarg,
+                _ => return None,
+            };
+
+            // natural alignments are expressed as powers of 2 and equal the access width
+            Some((memarg.offset, 1 << memarg.max_align))
+        });
+        Ok(accesses.collect())
+    }
+
+    /// Counts the direct call sites targeting each function. Uncalled functions are omitted.
+    pub fn call_fan_in(&self) -> Result<HashMap<FunctionIndex, usize>> {
+        let funcs = (self.imports.len() + self.codes.len()) as u32;
+        let mut fan_in = HashMap::default();
+        for op in self.codes.iter().flat_map(|x| &x.expr) {
+            if let Operator::Call { function_index } = op {
+                if *function_index >= funcs {
+                    bail!("call to missing func @ index {}", function_index.red());
+                }
+                *fan_in
+                    .entry(FunctionIndex::from_u32(*function_index))
+                    .or_default() += 1;
+            }
+        }
+        Ok(fan_in)
+    }
+
+    /// Lists the local functions that nothing refers to: they're never called, exported,
+    /// placed in a table, referenced via `ref.func`, or run at start. Functions only called
+    /// by other dead functions aren't included, so removing these may reveal more.
+    pub fn dead_functions(&self) -> Result<Vec<LocalFunctionIndex>> {
+        let imports = self.imports.len() as u32;
+        let mut live: HashSet<u32> = self.call_fan_in()?.keys().map(|x| x.as_u32()).collect();
+
+        live.extend(self.element_funcs()?);
+        live.extend(self.start);
+        for &(index, kind) in self.exports.values() {
+            if kind == ExportKind::Func {
+                live.insert(index);
+            }
+        }
+        for op in self.codes.iter().flat_map(|x| &x.expr) {
+            if let Operator::RefFunc { function_index } = op {
+                live.insert(*function_index);
+            }
+        }
+
+        let funcs = imports..imports + self.codes.len() as u32;
+        let dead = funcs.filter(|x| !live.contains(x)).map(|x| x - imports);
+        Ok(dead.map(LocalFunctionIndex::from_u32).collect())
+    }
+
+    /// Builds the static call graph, mapping each local function to the functions it directly
+    /// calls in order of first appearance. Indirect calls aren't included.
+    pub fn call_graph(&self) -> Result<HashMap<FunctionIndex, Vec<FunctionIndex>>> {
+        let imports = self.imports.len() as u32;
+        let funcs = imports + self.codes.len() as u32;
+        let mut graph = HashMap::default();
+
+        for (local, code) in self.codes.iter().enumerate() {
+            let mut callees = vec![];
+            for op in &code.expr {
+                let Operator::Call { function_index } = op else {
+                    continue;
+                };
+                if *function_index >= funcs {
+                    bail!("call to missing func @ index {}", function_index.red());
+                }
+                let callee = FunctionIndex::from_u32(*function_index);
+                if !callees.contains(&callee) {
+                    callees.push(callee);
+                }
+            }
+            graph.insert(FunctionIndex::from_u32(imports + local as u32), callees);
+        }
+        Ok(graph)
+    }
+
+    /// Computes the number of functions in the longest chain of direct calls starting at `entry`.
+    /// Imports count as leaves, indirect calls aren't followed, and any recursion is an error.
+    pub fn max_call_depth(&self, entry: FunctionIndex) -> Result<u32> {
+        let imports = self.imports.len() as u32;
+        let funcs = imports + self.codes.len() as u32;
+
+        // the depth of each function, once known
+        let mut depths: HashMap<u32, u32> = HashMap::default();
+        let mut on_path = HashSet::default();
+        let mut stack = vec![(entry.as_u32(), false)];
+
+        while let Some((func, visited)) = stack.pop() {
+            if func >= funcs {
+                bail!("reference to missing func @ index {}", func.red());
+            }
+            let Some(local) = func.checked_sub(imports) else {
+                depths.insert(func, 1);
+                continue;
+            };
+            let callees = self.codes[local as usize]
+                .expr
+                .iter()
+                .filter_map(|op| match op {
+                    Operator::Call { function_index } => Some(*function_index),
+                    _ => None,
+                });
+
+            if visited {
+                let deepest = callees.map(|x| depths[&x]).max().unwrap_or(0);
+                depths.insert(func, deepest + 1);
+                on_path.remove(&func);
+                continue;
+            }
+            if depths.contains_key(&func) {
+                continue;
+            }
+            on_path.insert(func);
+            stack.push((func, true));
+
+            for callee in callees {
+                if on_path.contains(&callee) {
+                    let callee = callee.red();
+                    bail!("func {} recursively calls func {callee}", func.red());
+                }
+                if !depths.contains_key(&callee) {
+                    stack.push((callee, false));
+                }
+            }
+        }
+        Ok(depths[&entry.as_u32()])
+    }
+
+    /// Computes the most values a local function's operand stack may hold at once,
+    /// as the [`DepthChecker`](super::depth::DepthChecker) does when sizing its frame.
+    pub fn max_stack_height(&self, func: LocalFunctionIndex) -> Result<u32> {
+        let Some(code) = self.codes.get(func.as_u32() as usize) else {
+            bail!("missing local func @ index {}", func.as_u32().red())
+        };
+        let funcs = self.all_functions()?;
+        let sigs = self.all_signatures()?;
+        depth::max_stack_height(&code.expr, &funcs, &sigs)
+    }
+
+    /// Computes the largest [`Self::max_stack_height`] across all local functions, or 0 if none.
+    pub fn module_max_stack_height(&self) -> Result<u32> {
+        let funcs = self.all_functions()?;
+        let sigs = self.all_signatures()?;
+        let mut worst = 0;
+        for code in &self.codes {
+            worst = worst.max(depth::max_stack_height(&code.expr, &funcs, &sigs)?);
+        }
+        Ok(worst)
+    }
+}
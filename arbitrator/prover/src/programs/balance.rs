@@ -0,0 +1,88 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use arbutil::{operator::OperatorInfo, Color};
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Ensures each function's control flow is balanced, catching malformed streams produced by
+/// earlier middlewares. Each `block`, `loop`, `if`, and `try` must be closed before the `end`
+/// that closes the function itself, after which no operators may follow.
+///
+/// A stream missing its final `end` is only caught by the prover, which calls `finish` once a
+/// function's operators run out. Native builds have no such hook.
+#[derive(Debug, Default)]
+pub struct ControlBalanceCheck;
+
+impl<M: ModuleMod> Middleware<M> for ControlBalanceCheck {
+    type FM<'a> = FuncControlBalanceCheck;
+
+    fn update_module(&self, _: &mut M) -> Result<()> {
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncControlBalanceCheck {
+            func,
+            depth: 0,
+            ended: false,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "control balance check"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncControlBalanceCheck {
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+    /// The number of blocks currently open, excluding the function body
+    depth: u32,
+    /// Whether the function body has been closed
+    ended: bool,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncControlBalanceCheck {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        let func = self.func.as_u32().red();
+        if self.ended {
+            bail!("func {func} has {} after its final end", op.code().red())
+        }
+        match op {
+            Block { .. } | Loop { .. } | If { .. } | Try { .. } => self.depth += 1,
+            Else | Catch { .. } | CatchAll if self.depth == 0 => {
+                bail!("func {func} has {} outside any block", op.code().red())
+            }
+            End | Delegate { .. } => match self.depth.checked_sub(1) {
+                Some(depth) => self.depth = depth,
+                None if matches!(op, End) => self.ended = true,
+                None => bail!("func {func} has {} outside any block", op.code().red()),
+            },
+            _ => {}
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if !self.ended {
+            let func = self.func.as_u32().red();
+            let open = self.depth + 1;
+            bail!("func {func} ends with {} unclosed block(s)", open.red())
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "control balance check"
+    }
+}
@@ -3,18 +3,24 @@
 
 #![allow(clippy::field_reassign_with_default)]
 
-use crate::{programs::meter, value::FunctionType};
+use crate::{
+    programs::meter::{self, MeterGranularity},
+    value::FunctionType,
+};
+use arbutil::operator::{OperatorCategory, OperatorInfo};
 use derivative::Derivative;
-use fnv::FnvHashMap as HashMap;
+use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
 use std::fmt::Debug;
-use wasmer_types::{Pages, SignatureIndex, WASM_PAGE_SIZE};
+use wasmer_types::{Pages, SignatureIndex, WASM_MAX_PAGES, WASM_PAGE_SIZE};
 use wasmparser::Operator;
 
 #[cfg(feature = "native")]
 use {
     super::{
-        counter::Counter, depth::DepthChecker, dynamic::DynamicMeter, heap::HeapBound,
-        meter::Meter, start::StartMover, MiddlewareWrapper,
+        align::AlignmentCheck, constant::ConstBound, counter::Counter, depth::DepthChecker,
+        dynamic::DynamicMeter, elements::PassiveElementBound, features::FeatureCheck,
+        heap::HeapBound, limits::ModuleLimits, locals::LocalsBound, meter::Meter,
+        nonfloat::NonFloat, start::StartMover, watchdog::StepWatchdog, MiddlewareWrapper,
     },
     std::sync::Arc,
     wasmer::{Cranelift, CraneliftOptLevel, Store},
@@ -104,6 +110,74 @@ pub struct CompileMemoryParams {
     pub max_frame_size: u32,
     /// The maximum number of overlapping value lifetimes in a frame
     pub max_frame_contention: u16,
+    /// Whether to reject memory accesses that aren't naturally aligned
+    pub strict_alignment: bool,
+    /// The largest magnitude an `i64.const` may have, if limited
+    pub max_const_magnitude: Option<u64>,
+    /// The most `memory.grow` operators a module may contain, if limited
+    pub max_grow_sites: Option<u64>,
+    /// Whether to allow more than one memory, each bounded by `heap_bound`
+    pub allow_multi_memory: bool,
+    /// Whether to reject floating-point operators, globals, and signatures
+    pub forbid_floats: bool,
+    /// The most entries a module's passive element segments may have in total, if limited
+    pub max_passive_elements: Option<u64>,
+    /// The most functions a module may define, if limited
+    pub max_functions: Option<u32>,
+    /// The most functions a module may import, if limited
+    pub max_imports: Option<u32>,
+    /// The most globals a module may declare, if limited
+    pub max_globals: Option<u32>,
+    /// The most exports a module may declare, if limited
+    pub max_exports: Option<u32>,
+    /// The most locals a function may declare, excluding params, if limited
+    pub max_locals_per_function: Option<u32>,
+    /// The most passive data segments a module may declare, if limited
+    pub max_data_segments: Option<u32>,
+    /// The most bytes a module's passive data segments may hold in total, if limited
+    pub max_data_bytes: Option<u64>,
+    /// The proposals beyond the MVP that a module may use
+    pub proposals: AllowedProposals,
+}
+
+/// WebAssembly proposals that chains may choose to disallow.
+/// By default every proposal is allowed, leaving it to the parser and compiler to reject any
+/// they don't support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllowedProposals {
+    /// Whether to allow reference-typed values and the operators that produce and store them
+    pub reference_types: bool,
+}
+
+impl Default for AllowedProposals {
+    fn default() -> Self {
+        Self {
+            reference_types: true,
+        }
+    }
+}
+
+/// How a heap bound given in bytes becomes a whole number of pages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PageRounding {
+    /// Round down, so the bound never exceeds the bytes requested.
+    #[default]
+    Down,
+    /// Round up, so the bound never falls short of the bytes requested.
+    Up,
+}
+
+impl CompileMemoryParams {
+    /// Sets `heap_bound` from a limit in bytes, which needn't be a multiple of the page size.
+    /// The result is capped at the 4GB the address space allows.
+    pub fn set_heap_bound_bytes(&mut self, bytes: u64, rounding: PageRounding) {
+        let page = WASM_PAGE_SIZE as u64;
+        let pages = match rounding {
+            PageRounding::Down => bytes / page,
+            PageRounding::Up => bytes / page + u64::from(bytes % page != 0),
+        };
+        self.heap_bound = Pages(pages.min(WASM_MAX_PAGES.into()) as u32);
+    }
 }
 
 #[derive(Clone, Derivative)]
@@ -118,6 +192,61 @@ pub struct CompilePricingParams {
     pub memory_fill_ink: u64,
     /// Per-byte `MemoryCopy` cost
     pub memory_copy_ink: u64,
+    /// Per-page `MemoryGrow` cost, paid before growing whether or not the grow succeeds
+    pub memory_grow_ink: u64,
+    /// Cost of converting between an int and a float, overriding `costs` when set
+    pub conversion_ink: Option<u64>,
+    /// Cost of a typed `select` on 64-bit operands, overriding `costs` when set
+    pub wide_select_ink: Option<u64>,
+    /// Cost of `ref.func` and `ref.null`, overriding `costs` when set
+    pub reference_ink: Option<u64>,
+    /// Cost of `table.get` and `table.set`, overriding `costs` when set
+    pub table_access_ink: Option<u64>,
+    /// Per-local cost of setting up a function's frame, charged on entry
+    pub local_ink: u64,
+    /// Per-result cost of returning from a function, whether explicitly or at its final `end`
+    pub return_value_ink: u64,
+    /// Surcharges for calling imports, keyed by module and name
+    pub import_ink: HashMap<(String, String), u64>,
+    /// Local functions the meter skips, which mustn't loop or call
+    pub unmetered_funcs: HashSet<u32>,
+    /// The most ink checks the meter may inject into a module, if limited
+    pub max_ink_checks: Option<u64>,
+    /// Where the meter injects ink checks
+    pub granularity: MeterGranularity,
+    /// Ink costs of whole categories of operators, overriding `costs` when set
+    pub opcode_pricing: OpcodePricing,
+    /// Ink available to a start function run during instantiation, before any is bought
+    pub start_ink: u64,
+}
+
+/// Ink costs for categories of operators, e.g. to tune pricing for different hardware.
+/// Per-opcode overrides like `conversion_ink` take precedence over these.
+#[derive(Clone, Debug, Default)]
+pub struct OpcodePricing {
+    /// Costs keyed by category
+    pub categories: HashMap<OperatorCategory, u64>,
+    /// Cost of any operator whose category isn't priced, deferring to `costs` when unset
+    pub default_cost: Option<u64>,
+}
+
+impl OpcodePricing {
+    pub fn with_category(mut self, category: OperatorCategory, cost: u64) -> Self {
+        self.categories.insert(category, cost);
+        self
+    }
+
+    pub fn with_default(mut self, cost: u64) -> Self {
+        self.default_cost = Some(cost);
+        self
+    }
+
+    /// The cost of an operator, if priced.
+    pub fn cost(&self, op: &Operator) -> Option<u64> {
+        let category = op.code().category();
+        let priced = self.categories.get(&category).copied();
+        priced.or(self.default_cost)
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -128,6 +257,8 @@ pub struct CompileDebugParams {
     pub count_ops: bool,
     /// Whether to use the Cranelift compiler
     pub cranelift: bool,
+    /// The most operators a program may execute regardless of ink, if limited
+    pub max_steps: Option<u64>,
 }
 
 impl Default for CompilePricingParams {
@@ -137,6 +268,19 @@ impl Default for CompilePricingParams {
             ink_header_cost: 0,
             memory_fill_ink: 0,
             memory_copy_ink: 0,
+            memory_grow_ink: 0,
+            conversion_ink: None,
+            wide_select_ink: None,
+            reference_ink: None,
+            table_access_ink: None,
+            local_ink: 0,
+            return_value_ink: 0,
+            import_ink: HashMap::default(),
+            unmetered_funcs: HashSet::default(),
+            max_ink_checks: None,
+            granularity: MeterGranularity::BasicBlock,
+            opcode_pricing: OpcodePricing::default(),
+            start_ink: 0,
         }
     }
 }
@@ -147,6 +291,20 @@ impl Default for CompileMemoryParams {
             heap_bound: Pages(u32::MAX / WASM_PAGE_SIZE as u32),
             max_frame_size: u32::MAX,
             max_frame_contention: u16::MAX,
+            strict_alignment: false,
+            max_const_magnitude: None,
+            max_grow_sites: None,
+            allow_multi_memory: false,
+            forbid_floats: false,
+            max_passive_elements: None,
+            max_functions: None,
+            max_imports: None,
+            max_globals: None,
+            max_exports: None,
+            max_locals_per_function: None,
+            max_data_segments: None,
+            max_data_bytes: None,
+            proposals: AllowedProposals::default(),
         }
     }
 }
@@ -169,6 +327,19 @@ impl CompileConfig {
                     ink_header_cost: 2450,
                     memory_fill_ink: 800 / 8,
                     memory_copy_ink: 800 / 8,
+                    memory_grow_ink: 0,
+                    conversion_ink: None,
+                    wide_select_ink: None,
+                    reference_ink: None,
+                    table_access_ink: None,
+                    local_ink: 0,
+                    return_value_ink: 0,
+                    import_ink: HashMap::default(),
+                    unmetered_funcs: HashSet::default(),
+                    max_ink_checks: None,
+                    granularity: MeterGranularity::BasicBlock,
+                    opcode_pricing: OpcodePricing::default(),
+                    start_ink: 0,
                 };
             }
             _ => panic!("no config exists for Stylus version {version}"),
@@ -190,20 +361,45 @@ impl CompileConfig {
         compiler.canonicalize_nans(true);
         compiler.enable_verifier();
 
+        let limits = MiddlewareWrapper::new(ModuleLimits::new(self.bounds));
         let meter = MiddlewareWrapper::new(Meter::new(&self.pricing));
         let dygas = MiddlewareWrapper::new(DynamicMeter::new(&self.pricing));
         let depth = MiddlewareWrapper::new(DepthChecker::new(self.bounds));
         let bound = MiddlewareWrapper::new(HeapBound::new(self.bounds));
         let start = MiddlewareWrapper::new(StartMover::default());
+        let align = MiddlewareWrapper::new(AlignmentCheck::new(self.bounds));
+        let consts = MiddlewareWrapper::new(ConstBound::new(self.bounds));
 
         // add the instrumentation in the order of application
         // note: this must be consistent with the prover
+        compiler.push_middleware(Arc::new(limits)); // precedes any added globals or exports
+        compiler.push_middleware(Arc::new(consts)); // precedes the meter's own constants
         compiler.push_middleware(Arc::new(meter));
         compiler.push_middleware(Arc::new(dygas));
         compiler.push_middleware(Arc::new(depth));
         compiler.push_middleware(Arc::new(bound));
         compiler.push_middleware(Arc::new(start));
+        compiler.push_middleware(Arc::new(align));
 
+        if let Some(limit) = self.bounds.max_passive_elements {
+            let bound = PassiveElementBound::new(limit);
+            compiler.push_middleware(Arc::new(MiddlewareWrapper::new(bound)));
+        }
+        if self.bounds.forbid_floats {
+            compiler.push_middleware(Arc::new(MiddlewareWrapper::new(NonFloat)));
+        }
+        if self.bounds.proposals != AllowedProposals::default() {
+            let check = FeatureCheck::new(self.bounds.proposals);
+            compiler.push_middleware(Arc::new(MiddlewareWrapper::new(check)));
+        }
+        if let Some(limit) = self.bounds.max_locals_per_function {
+            let bound = LocalsBound::new(limit);
+            compiler.push_middleware(Arc::new(MiddlewareWrapper::new(bound)));
+        }
+        if let Some(limit) = self.debug.max_steps {
+            let watchdog = StepWatchdog::new(limit);
+            compiler.push_middleware(Arc::new(MiddlewareWrapper::new(watchdog)));
+        }
         if self.debug.count_ops {
             let counter = Counter::new();
             compiler.push_middleware(Arc::new(MiddlewareWrapper::new(counter)));
@@ -0,0 +1,73 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{config::CompileMemoryParams, FuncMiddleware, Middleware, ModuleMod};
+use arbutil::Color;
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Optionally caps the magnitude of `i64.const` immediates, since huge ones can signal abuse.
+#[derive(Debug)]
+pub struct ConstBound {
+    /// The largest magnitude allowed, if any
+    limit: Option<u64>,
+}
+
+impl ConstBound {
+    pub fn new(bounds: CompileMemoryParams) -> Self {
+        Self {
+            limit: bounds.max_const_magnitude,
+        }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for ConstBound {
+    type FM<'a> = FuncConstBound;
+
+    fn update_module(&self, _: &mut M) -> Result<()> {
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncConstBound {
+            limit: self.limit,
+            func,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "const bound"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncConstBound {
+    /// The largest magnitude allowed, if any
+    limit: Option<u64>,
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncConstBound {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        if let (Some(limit), Operator::I64Const { value }) = (self.limit, &op) {
+            if value.unsigned_abs() > limit {
+                let func = self.func.as_u32().red();
+                bail!(
+                    "func {func} has constant {} exceeding limit {limit}",
+                    value.red()
+                )
+            }
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "const bound"
+    }
+}
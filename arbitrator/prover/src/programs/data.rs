@@ -0,0 +1,94 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware};
+use crate::binary::WasmBinary;
+use arbutil::Color;
+use eyre::{bail, Result};
+use parking_lot::RwLock;
+use std::sync::Arc;
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::{DataKind, Operator};
+
+/// Ensures each data segment is used in a manner consistent with its mode.
+/// Active segments must target a declared memory, and since they're dropped at instantiation,
+/// only passive segments may be the source of a `memory.init`.
+#[derive(Debug, Default)]
+pub struct DataSegmentCheck {
+    /// Whether each segment is passive, indexed by segment
+    passive: RwLock<Option<Arc<Vec<bool>>>>,
+}
+
+impl<'a> Middleware<WasmBinary<'a>> for DataSegmentCheck {
+    type FM<'b> = FuncDataSegmentCheck;
+
+    fn update_module(&self, module: &mut WasmBinary<'a>) -> Result<()> {
+        let memories = module.memories.len() as u32;
+        let mut passive = Vec::with_capacity(module.datas.len());
+
+        for (index, data) in module.datas.iter().enumerate() {
+            match data.kind {
+                DataKind::Active { memory_index, .. } if memory_index >= memories => {
+                    let memory = memory_index.red();
+                    bail!(
+                        "data segment {} targets missing memory {memory}",
+                        index.red()
+                    )
+                }
+                DataKind::Active { .. } => passive.push(false),
+                DataKind::Passive => passive.push(true),
+            }
+        }
+        *self.passive.write() = Some(Arc::new(passive));
+        Ok(())
+    }
+
+    fn instrument<'b>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'b>> {
+        let passive = self.passive.read().clone().expect("no segments");
+        Ok(FuncDataSegmentCheck { passive, func })
+    }
+
+    fn name(&self) -> &'static str {
+        "data segment check"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncDataSegmentCheck {
+    /// Whether each segment is passive, indexed by segment
+    passive: Arc<Vec<bool>>,
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncDataSegmentCheck {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        let func = self.func.as_u32().red();
+        match op {
+            MemoryInit { data_index, .. } | DataDrop { data_index } => {
+                let Some(&passive) = self.passive.get(data_index as usize) else {
+                    bail!(
+                        "func {func} references missing data segment {}",
+                        data_index.red()
+                    )
+                };
+                if !passive && matches!(op, MemoryInit { .. }) {
+                    let segment = data_index.red();
+                    bail!("func {func} initializes memory from active data segment {segment}")
+                }
+            }
+            _ => {}
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "data segment check"
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{config::CompileConfig, Middleware, StylusData};
+use crate::{
+    binary::{ExportKind, WasmBinary},
+    value::{ArbValueType, Value},
+};
+use eyre::Result;
+use fnv::FnvHashSet as HashSet;
+
+/// A coarse record of what instrumentation changed in a module, for auditing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstrumentationDiff {
+    /// Exports added, such as for the globals the middlewares inject
+    pub added_exports: Vec<(String, ExportKind)>,
+    /// Exports removed
+    pub removed_exports: Vec<String>,
+    /// The number of globals added
+    pub added_globals: usize,
+    /// Imports added, by module and name
+    pub added_imports: Vec<(String, String)>,
+    /// The start function, if it was removed
+    pub removed_start: Option<u32>,
+    /// Local functions whose bodies changed, along with their opcode counts before and after
+    pub modified_funcs: Vec<(u32, usize, usize)>,
+}
+
+impl InstrumentationDiff {
+    /// Compares a module to its instrumented counterpart.
+    pub fn between(before: &WasmBinary, after: &WasmBinary) -> Self {
+        let mut diff = Self::default();
+
+        for (name, &(_, kind)) in &after.exports {
+            if !before.exports.contains_key(name) {
+                diff.added_exports.push((name.clone(), kind));
+            }
+        }
+        for name in before.exports.keys() {
+            if !after.exports.contains_key(name) {
+                diff.removed_exports.push(name.clone());
+            }
+        }
+        diff.added_exports.sort_by(|a, b| a.0.cmp(&b.0));
+        diff.removed_exports.sort();
+        diff.added_globals = after.globals.len().saturating_sub(before.globals.len());
+
+        let imports: HashSet<_> = before.imports.iter().map(|x| (x.module, x.name)).collect();
+        for import in &after.imports {
+            if !imports.contains(&(import.module, import.name)) {
+                let import = (import.module.to_owned(), import.name.to_owned());
+                diff.added_imports.push(import);
+            }
+        }
+        if after.start.is_none() {
+            diff.removed_start = before.start;
+        }
+
+        for (index, (old, new)) in before.codes.iter().zip(&after.codes).enumerate() {
+            let (old, new) = (old.expr.len(), new.expr.len());
+            if old != new {
+                diff.modified_funcs.push((index as u32, old, new));
+            }
+        }
+        diff
+    }
+}
+
+/// What a middleware's module-level pass changes, as reported by [`WasmBinary::dry_run`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleDiff {
+    /// Globals added, along with their export names, types, and initial values
+    pub added_globals: Vec<(String, ArbValueType, Value)>,
+    /// The start function and the name it was exported under, if moved
+    pub moved_start: Option<(u32, String)>,
+    /// Memories whose maximum changed, along with their old and new maximums in pages
+    pub memory_maximums: Vec<(u32, Option<u64>, Option<u64>)>,
+    /// Exports now known by another name, from old to new
+    pub renamed_exports: Vec<(String, String)>,
+}
+
+impl ModuleDiff {
+    /// Compares a module to its counterpart after a module-level pass.
+    pub fn between(before: &WasmBinary, after: &WasmBinary) -> Self {
+        let mut diff = Self::default();
+        let exports = &before.exports;
+        let added = || after.exports.iter().filter(|x| !exports.contains_key(x.0));
+
+        for (name, &(index, kind)) in &after.exports {
+            let index = index as usize;
+            if kind == ExportKind::Global && index >= before.globals.len() {
+                let value = after.globals[index];
+                diff.added_globals.push((name.clone(), value.ty(), value));
+            }
+        }
+        diff.added_globals.sort_by(|a, b| a.0.cmp(&b.0));
+
+        if let (Some(start), None) = (before.start, after.start) {
+            let func = (start, ExportKind::Func);
+            let moved = added().find(|x| *x.1 == func);
+            diff.moved_start = moved.map(|(name, _)| (start, name.clone()));
+        }
+
+        for (index, (old, new)) in before.memories.iter().zip(&after.memories).enumerate() {
+            let (old, new) = (old.maximum, new.maximum);
+            if old != new {
+                diff.memory_maximums.push((index as u32, old, new));
+            }
+        }
+
+        for (old, export) in &before.exports {
+            if after.exports.contains_key(old) {
+                continue;
+            }
+            if let Some((new, _)) = added().find(|x| x.1 == export) {
+                diff.renamed_exports.push((old.clone(), new.clone()));
+            }
+        }
+        diff.renamed_exports.sort();
+        diff
+    }
+}
+
+impl<'a> WasmBinary<'a> {
+    /// Runs a middleware's module-level pass against a copy of the module, reporting what it
+    /// would change without instrumenting any function bodies. The middleware's own state is
+    /// updated as usual, so it shouldn't be reused for a real run.
+    pub fn dry_run<M>(&self, middleware: &M) -> Result<ModuleDiff>
+    where
+        M: Middleware<WasmBinary<'a>>,
+    {
+        let mut after = self.clone();
+        middleware.update_module(&mut after)?;
+        Ok(ModuleDiff::between(self, &after))
+    }
+
+    /// Instruments a user wasm like [`WasmBinary::instrument`], also recording what changed.
+    pub fn instrument_with_diff(
+        &mut self,
+        compile: &CompileConfig,
+    ) -> Result<(StylusData, InstrumentationDiff)> {
+        let before = self.clone();
+        let data = self.instrument(compile)?;
+        Ok((data, InstrumentationDiff::between(&before, self)))
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use eyre::Result;
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Removes values that are produced only to be dropped immediately after.
+/// To be conservative, only constants and reads of locals and globals are removed.
+#[derive(Debug, Default)]
+pub struct DropElimination;
+
+impl<M: ModuleMod> Middleware<M> for DropElimination {
+    type FM<'a> = FuncDropElimination<'a>;
+
+    fn update_module(&self, _: &mut M) -> Result<()> {
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncDropElimination::default())
+    }
+
+    fn name(&self) -> &'static str {
+        "drop elimination"
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FuncDropElimination<'a> {
+    /// A side-effect-free producer that may yet be dropped
+    pending: Option<Operator<'a>>,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncDropElimination<'a> {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        if let (Some(_), Drop) = (&self.pending, &op) {
+            self.pending = None;
+            return Ok(());
+        }
+        out.extend(self.pending.take());
+
+        match op {
+            I32Const { .. }
+            | I64Const { .. }
+            | F32Const { .. }
+            | F64Const { .. }
+            | LocalGet { .. }
+            | GlobalGet { .. } => self.pending = Some(op),
+            _ => out.extend([op]),
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "drop elimination"
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{DefaultFuncMiddleware, Middleware, ModuleMod};
+use arbutil::Color;
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+
+/// Bounds the total size of a module's passive element segments, which `table.init` may copy from
+/// repeatedly and which must be retained for the lifetime of the program.
+#[derive(Debug)]
+pub struct PassiveElementBound {
+    /// The most entries the passive segments may have in total
+    limit: u64,
+}
+
+impl PassiveElementBound {
+    pub fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for PassiveElementBound {
+    type FM<'a> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let count = module.passive_element_count() as u64;
+        if count > self.limit {
+            let limit = self.limit.red();
+            bail!(
+                "module has {} passive element entries, exceeding the limit of {limit}",
+                count.red()
+            );
+        }
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "passive element bound"
+    }
+}
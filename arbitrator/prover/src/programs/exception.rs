@@ -0,0 +1,63 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use arbutil::{operator::OperatorInfo, Color};
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Rejects operators from the exception-handling proposal, which the prover doesn't support.
+#[derive(Debug, Default)]
+pub struct ExceptionCheck;
+
+impl<M: ModuleMod> Middleware<M> for ExceptionCheck {
+    type FM<'a> = FuncExceptionCheck;
+
+    fn update_module(&self, _: &mut M) -> Result<()> {
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncExceptionCheck { func })
+    }
+
+    fn name(&self) -> &'static str {
+        "exception check"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncExceptionCheck {
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncExceptionCheck {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        macro_rules! dot {
+            ($first:ident $(,$opcode:ident)*) => {
+                $first { .. } $(| $opcode { .. })*
+            };
+        }
+
+        if matches!(op, dot!(Try, Catch, Throw, Rethrow, Delegate) | CatchAll) {
+            let func = self.func.as_u32().red();
+            bail!(
+                "func {func} uses unsupported exception opcode {}",
+                op.code().red()
+            )
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "exception check"
+    }
+}
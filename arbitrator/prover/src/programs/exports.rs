@@ -0,0 +1,51 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{DefaultFuncMiddleware, Middleware};
+use crate::binary::WasmBinary;
+use eyre::Result;
+use wasmer_types::LocalFunctionIndex;
+
+#[cfg(feature = "native")]
+use wasmer_types::ModuleInfo;
+
+/// Puts a module's exports in order of name, for reproducible builds.
+/// Wasmer keeps exports in the order the module listed them, so modules listing the same exports
+/// differently would otherwise be processed differently. Afterward, modules with the same exports
+/// iterate over them identically.
+#[derive(Debug, Default)]
+pub struct CanonicalizeExports;
+
+impl<'a> Middleware<WasmBinary<'a>> for CanonicalizeExports {
+    type FM<'b> = DefaultFuncMiddleware;
+
+    fn update_module(&self, _: &mut WasmBinary<'a>) -> Result<()> {
+        Ok(()) // an `ExportMap` is always ordered by name
+    }
+
+    fn instrument<'b>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'b>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "canonicalize exports"
+    }
+}
+
+#[cfg(feature = "native")]
+impl Middleware<ModuleInfo> for CanonicalizeExports {
+    type FM<'b> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut ModuleInfo) -> Result<()> {
+        module.exports.sort_keys();
+        Ok(())
+    }
+
+    fn instrument<'b>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'b>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "canonicalize exports"
+    }
+}
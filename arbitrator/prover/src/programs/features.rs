@@ -0,0 +1,126 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{config::AllowedProposals, FuncMiddleware, LocalsError, Middleware, ModuleMod};
+use crate::value::ArbValueType;
+use arbutil::{operator::OperatorInfo, Color};
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::{Operator, ValType};
+
+/// Rejects modules relying on proposals the chain doesn't allow, whether through operators,
+/// locals, globals, signatures, or tables. Since `funcref` tables predate the reference-types
+/// proposal, they're always allowed. GC opcodes aren't checked since the parser predates them.
+#[derive(Debug)]
+pub struct FeatureCheck {
+    /// The proposals a module may use
+    allowed: AllowedProposals,
+}
+
+impl FeatureCheck {
+    pub fn new(allowed: AllowedProposals) -> Self {
+        Self { allowed }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for FeatureCheck {
+    type FM<'a> = FuncFeatureCheck;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        if self.allowed.reference_types {
+            return Ok(());
+        }
+        use ArbValueType::*;
+        let is_ref = |ty: &ArbValueType| matches!(ty, RefNull | FuncRef | InternalRef);
+
+        for global in 0..module.global_count() {
+            let ty = module.global_type(global)?;
+            if is_ref(&ty) {
+                bail!("global {} has reference type {}", global.red(), ty.red())
+            }
+        }
+        for (sig, ty) in module.all_signatures()? {
+            if ty.inputs.iter().chain(&ty.outputs).any(is_ref) {
+                let sig = sig.as_u32().red();
+                bail!("signature {sig} has reference type {}", ty.red())
+            }
+        }
+        for table in 0..module.table_count() {
+            if module.table_element_type(table)? == ValType::ExternRef {
+                bail!("table {} holds externrefs", table.red())
+            }
+        }
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncFeatureCheck {
+            func,
+            allowed: self.allowed,
+            error: LocalsError::default(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "feature check"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncFeatureCheck {
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+    /// The proposals a module may use
+    allowed: AllowedProposals,
+    /// Set when the function declares a disallowed reference-typed local
+    error: LocalsError,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncFeatureCheck {
+    fn locals_info(&mut self, locals: &[ValType]) {
+        let is_ref = |ty: &ValType| matches!(ty, ValType::FuncRef | ValType::ExternRef);
+        if !self.allowed.reference_types && locals.iter().any(is_ref) {
+            let func = self.func.as_u32().red();
+            self.error
+                .set(format!("func {func} declares a reference-typed local"));
+        }
+    }
+
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        macro_rules! dot {
+            ($first:ident $(,$opcode:ident)*) => {
+                $first { .. } $(| $opcode { .. })*
+            };
+        }
+
+        self.error.check()?;
+
+        if !self.allowed.reference_types {
+            let func = self.func.as_u32().red();
+
+            #[rustfmt::skip]
+            let reference = matches!(
+                op,
+                RefIsNull | dot!(RefNull, RefFunc, TableGet, TableSet, TableGrow, TableSize, TableFill) |
+                TypedSelect { ty: ValType::FuncRef | ValType::ExternRef }
+            );
+            if reference {
+                bail!(
+                    "func {func} uses reference-types operator {}",
+                    op.code().red()
+                )
+            }
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "feature check"
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use arbutil::Color;
+use eyre::{bail, Result};
+use parking_lot::RwLock;
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Ensures every `global.get` and `global.set` refers to a global that exists,
+/// including those injected by earlier middlewares.
+#[derive(Debug, Default)]
+pub struct GlobalCheck {
+    /// The number of globals in the module
+    count: RwLock<Option<u32>>,
+}
+
+impl<M: ModuleMod> Middleware<M> for GlobalCheck {
+    type FM<'a> = FuncGlobalCheck;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        *self.count.write() = Some(module.global_count());
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        let count = self.count.read().expect("no global count");
+        Ok(FuncGlobalCheck { count, func })
+    }
+
+    fn name(&self) -> &'static str {
+        "global check"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncGlobalCheck {
+    /// The number of globals in the module
+    count: u32,
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncGlobalCheck {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        if let GlobalGet { global_index } | GlobalSet { global_index } = op {
+            if global_index >= self.count {
+                let func = self.func.as_u32().red();
+                bail!(
+                    "func {func} references missing global {}",
+                    global_index.red()
+                )
+            }
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "global check"
+    }
+}
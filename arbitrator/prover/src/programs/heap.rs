@@ -5,10 +5,15 @@ use crate::value::{ArbValueType, FunctionType};
 
 use super::{
     config::CompileMemoryParams, dynamic::SCRATCH_GLOBAL, FuncMiddleware, Middleware, ModuleMod,
+    ModuleModError,
 };
 use arbutil::Color;
 use eyre::{bail, Result};
 use parking_lot::RwLock;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use wasmer_types::{FunctionIndex, GlobalIndex, ImportIndex, LocalFunctionIndex, Pages};
 use wasmparser::Operator;
 
@@ -16,6 +21,12 @@ use wasmparser::Operator;
 pub struct HeapBound {
     /// Upper bounds the amount of heap memory a module may use
     limit: Pages,
+    /// Whether the module may have more than one memory
+    multi_memory: bool,
+    /// The most `memory.grow` operators the module may contain, if limited
+    max_grow_sites: Option<u64>,
+    /// The number of `memory.grow` operators seen so far
+    grow_sites: Arc<AtomicU64>,
     /// Import called when allocating new pages
     pay_func: RwLock<Option<FunctionIndex>>,
     /// Scratch global shared among middlewares
@@ -28,6 +39,9 @@ impl HeapBound {
     pub fn new(bounds: CompileMemoryParams) -> Self {
         Self {
             limit: bounds.heap_bound,
+            multi_memory: bounds.allow_multi_memory,
+            max_grow_sites: bounds.max_grow_sites,
+            grow_sites: Arc::default(),
             pay_func: RwLock::default(),
             scratch: RwLock::default(),
         }
@@ -40,16 +54,31 @@ impl<M: ModuleMod> Middleware<M> for HeapBound {
     fn update_module(&self, module: &mut M) -> Result<()> {
         let scratch = module.get_global(SCRATCH_GLOBAL)?;
         *self.scratch.write() = Some(scratch);
+        self.grow_sites.store(0, Ordering::Relaxed);
 
-        let memory = module.memory_info()?;
-        let min = memory.min;
-        let max = memory.max;
-        let lim = self.limit;
+        let count = module.memory_count();
+        if count > 1 && !self.multi_memory {
+            bail!(ModuleModError::MultiMemoryUnsupported);
+        }
+        if count <= 1 {
+            module.memory_info()?; // ensures the memory is exported
+        }
 
-        if min > lim {
-            bail!("memory size {} exceeds bound {}", min.0.red(), lim.0.red());
+        // each memory is bounded individually
+        let limit = self.limit;
+        let mut fixed = true;
+        for memory in 0..count {
+            let minimum = module.memory_minimum(memory)?;
+            if minimum > limit {
+                bail!(ModuleModError::MemoryMinimumExceedsLimit {
+                    memory,
+                    minimum,
+                    limit,
+                });
+            }
+            fixed &= module.memory_maximum(memory)? == Some(minimum);
         }
-        if max == Some(min) {
+        if fixed {
             return Ok(());
         }
 
@@ -70,18 +99,28 @@ impl<M: ModuleMod> Middleware<M> for HeapBound {
         Ok(FuncHeapBound {
             scratch: self.scratch.read().expect("no scratch global"),
             pay_func: *self.pay_func.read(),
+            max_grow_sites: self.max_grow_sites,
+            grow_sites: self.grow_sites.clone(),
         })
     }
 
     fn name(&self) -> &'static str {
         "heap bound"
     }
+
+    fn metrics(&self, sink: &mut dyn FnMut(&str, u64)) {
+        sink("memory.grow sites", self.grow_sites.load(Ordering::Relaxed));
+    }
 }
 
 #[derive(Debug)]
 pub struct FuncHeapBound {
     pay_func: Option<FunctionIndex>,
     scratch: GlobalIndex,
+    /// The most `memory.grow` operators the module may contain, if limited
+    max_grow_sites: Option<u64>,
+    /// The number of `memory.grow` operators seen so far
+    grow_sites: Arc<AtomicU64>,
 }
 
 impl<'a> FuncMiddleware<'a> for FuncHeapBound {
@@ -91,6 +130,13 @@ impl<'a> FuncMiddleware<'a> for FuncHeapBound {
     {
         use Operator::*;
 
+        if let MemoryGrow { .. } = op {
+            let sites = self.grow_sites.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(max) = self.max_grow_sites.filter(|&max| sites > max) {
+                bail!("module has more than {} memory.grow sites", max.red());
+            }
+        }
+
         let Some(pay_func) = self.pay_func else {
             out.extend([op]);
             return Ok(());
@@ -0,0 +1,50 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{DefaultFuncMiddleware, Middleware, ModuleMod};
+use crate::value::FunctionType as ArbFunctionType;
+use arbutil::Color;
+use eyre::{bail, Result};
+use fnv::FnvHashMap as HashMap;
+use wasmer_types::LocalFunctionIndex;
+
+/// Rejects modules importing functions outside a known set of host functions,
+/// or importing known ones with the wrong signature.
+#[derive(Debug)]
+pub struct ImportAllowlist {
+    /// The signature each permitted import must have, keyed by module and name
+    allowed: HashMap<(String, String), ArbFunctionType>,
+}
+
+impl ImportAllowlist {
+    pub fn new(allowed: HashMap<(String, String), ArbFunctionType>) -> Self {
+        Self { allowed }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for ImportAllowlist {
+    type FM<'a> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        for (import, name, func) in module.func_imports() {
+            let path = format!("{import}::{name}");
+            let Some(expected) = self.allowed.get(&(import, name)) else {
+                bail!("import {} is not an available host function", path.red())
+            };
+            let ty = module.get_function(func)?;
+            if &ty != expected {
+                let (ty, expected) = (ty.red(), expected.red());
+                bail!("import {} has type {ty} instead of {expected}", path.red())
+            }
+        }
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "import allowlist"
+    }
+}
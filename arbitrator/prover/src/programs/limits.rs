@@ -0,0 +1,93 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{config::CompileMemoryParams, DefaultFuncMiddleware, Middleware, ModuleMod};
+use arbutil::Color;
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+
+/// Bounds the number of functions, imports, globals, exports, and passive data segments a module
+/// may declare, as well as the total size of its passive data segments. Active segments aren't
+/// bounded, since `ModuleInfo` doesn't retain them and they're dropped once applied anyway.
+/// Since later middlewares add globals and exports of their own, this should run first.
+/// Each count must come out the same from `ModuleInfo` and `WasmBinary`, or native and prover
+/// builds would disagree on which modules are valid.
+#[derive(Debug)]
+pub struct ModuleLimits {
+    /// The most functions a module may define, if limited
+    functions: Option<u32>,
+    /// The most functions a module may import, if limited
+    imports: Option<u32>,
+    /// The most globals a module may declare, if limited
+    globals: Option<u32>,
+    /// The most exports a module may declare, if limited
+    exports: Option<u32>,
+    /// The most passive data segments a module may declare, if limited
+    data_segments: Option<u32>,
+    /// The most bytes a module's passive data segments may hold in total, if limited
+    data_bytes: Option<u64>,
+}
+
+impl ModuleLimits {
+    pub fn new(bounds: CompileMemoryParams) -> Self {
+        Self {
+            functions: bounds.max_functions,
+            imports: bounds.max_imports,
+            globals: bounds.max_globals,
+            exports: bounds.max_exports,
+            data_segments: bounds.max_data_segments,
+            data_bytes: bounds.max_data_bytes,
+        }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for ModuleLimits {
+    type FM<'a> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let counts = [
+            (
+                "functions",
+                module.num_defined_functions() as u32,
+                self.functions,
+            ),
+            ("imports", module.func_import_count(), self.imports),
+            ("globals", module.global_count(), self.globals),
+            ("exports", module.export_count(), self.exports),
+            (
+                "passive data segments",
+                module.passive_data_count() as u32,
+                self.data_segments,
+            ),
+        ];
+        for (kind, count, limit) in counts {
+            let Some(limit) = limit else { continue };
+            if count > limit {
+                let limit = limit.red();
+                bail!(
+                    "module has {} {kind}, exceeding the limit of {limit}",
+                    count.red()
+                );
+            }
+        }
+        if let Some(limit) = self.data_bytes {
+            let bytes = module.passive_data_bytes() as u64;
+            if bytes > limit {
+                let limit = limit.red();
+                bail!(
+                    "module has {} bytes of passive data, exceeding the limit of {limit}",
+                    bytes.red()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "module limits"
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, LocalsError, Middleware, ModuleMod};
+use arbutil::Color;
+use eyre::Result;
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::{Operator, ValType};
+
+/// Bounds the number of locals each function may declare, since compilers allocate space for
+/// every one of them regardless of use. Params aren't counted.
+#[derive(Debug)]
+pub struct LocalsBound {
+    /// The most locals a function may declare
+    limit: u32,
+}
+
+impl LocalsBound {
+    pub fn new(limit: u32) -> Self {
+        Self { limit }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for LocalsBound {
+    type FM<'a> = FuncLocalsBound;
+
+    fn update_module(&self, _: &mut M) -> Result<()> {
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncLocalsBound {
+            func,
+            limit: self.limit,
+            error: LocalsError::default(),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "locals bound"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncLocalsBound {
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+    /// The most locals a function may declare
+    limit: u32,
+    /// Set when the function declares too many locals
+    error: LocalsError,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncLocalsBound {
+    fn locals_info(&mut self, locals: &[ValType]) {
+        if locals.len() > self.limit as usize {
+            let func = self.func.as_u32().red();
+            let limit = self.limit.red();
+            self.error.set(format!(
+                "func {func} declares {} locals, exceeding the limit of {limit}",
+                locals.len().red()
+            ));
+        }
+    }
+
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        self.error.check()?;
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "locals bound"
+    }
+}
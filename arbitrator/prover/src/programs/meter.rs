@@ -3,23 +3,31 @@
 
 use crate::{
     programs::{
-        config::{CompilePricingParams, PricingParams, SigMap},
+        config::{CompilePricingParams, OpcodePricing, PricingParams, SigMap},
         FuncMiddleware, Middleware, ModuleMod,
     },
     value::FunctionType,
     Machine,
 };
-use arbutil::{evm, operator::OperatorInfo};
+use arbutil::{evm, operator::OperatorInfo, Color};
 use derivative::Derivative;
-use eyre::Result;
-use fnv::FnvHashMap as HashMap;
+use eyre::{bail, Result};
+use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
 use parking_lot::RwLock;
 use std::{
     fmt::{Debug, Display},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use wasmer_types::{
+    FunctionIndex, GlobalIndex, GlobalInit, ImportIndex, LocalFunctionIndex, SignatureIndex, Type,
 };
-use wasmer_types::{GlobalIndex, GlobalInit, LocalFunctionIndex, SignatureIndex, Type};
-use wasmparser::{BlockType, Operator};
+use wasmparser::{BlockType, Operator, ValType};
+
+#[cfg(feature = "native")]
+use wasmer::{Instance, Store, Value as WasmerValue};
 
 use super::config::OpCosts;
 
@@ -30,6 +38,18 @@ pub trait OpcodePricer: Fn(&Operator, &SigMap) -> u64 + Send + Sync + Clone {}
 
 impl<T> OpcodePricer for T where T: Fn(&Operator, &SigMap) -> u64 + Send + Sync + Clone {}
 
+/// Where the meter injects ink checks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeterGranularity {
+    /// Charge for every operator, checking once per basic block.
+    #[default]
+    BasicBlock,
+    /// Charge only for calls to imports, leaving pure compute free.
+    /// Since a program may then loop forever without paying, this isn't consensus-safe for
+    /// compute-bound contracts and should only be used when something else bounds execution.
+    HostCallsOnly,
+}
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Meter<F: OpcodePricer> {
@@ -38,10 +58,47 @@ pub struct Meter<F: OpcodePricer> {
     costs: F,
     /// Cost of checking the amount of ink left.
     header_cost: u64,
+    /// Cost of converting between an int and a float, overriding `costs` when set.
+    conversion_cost: Option<u64>,
+    /// Cost of a typed `select` on 64-bit operands, overriding `costs` when set.
+    wide_select_cost: Option<u64>,
+    /// Cost of `ref.func` and `ref.null`, overriding `costs` when set.
+    reference_cost: Option<u64>,
+    /// Cost of `table.get` and `table.set`, overriding `costs` when set.
+    table_access_cost: Option<u64>,
+    /// Costs of whole categories of operators, overriding `costs` when set.
+    opcode_pricing: Arc<OpcodePricing>,
+    /// Per-local cost of setting up a function's frame.
+    local_cost: u64,
+    /// Per-result cost of returning from a function.
+    return_value_cost: u64,
+    /// The number of results each function returns, if returns are priced.
+    results: RwLock<HashMap<FunctionIndex, u64>>,
+    /// Surcharges for calling imports, keyed by module and name.
+    import_costs: HashMap<(String, String), u64>,
+    /// Local functions exempt from metering.
+    unmetered: HashSet<u32>,
+    /// The most ink checks that may be injected into the module, if limited.
+    max_checks: Option<u64>,
+    /// The number of ink checks injected into the module so far.
+    checks: Arc<AtomicU64>,
+    /// Where ink checks are injected.
+    granularity: MeterGranularity,
+    /// The number of imported functions, which precede local ones.
+    imports: RwLock<Option<u32>>,
+    /// The static ink cost of each function instrumented so far.
+    function_costs: Arc<RwLock<HashMap<FunctionIndex, u64>>>,
+    /// The module's start function, if it has one.
+    start: RwLock<Option<FunctionIndex>>,
+    /// The initial value of the ink global, which pays for any start function run during
+    /// instantiation since ink can't be bought until after.
+    start_ink: u64,
     /// Ink and ink status globals.
     globals: RwLock<Option<[GlobalIndex; 2]>>,
     /// The types of the module being instrumented
     sigs: RwLock<Option<Arc<SigMap>>>,
+    /// Surcharges for calling imports, keyed by function index.
+    call_costs: RwLock<Option<Arc<HashMap<u32, u64>>>>,
 }
 
 impl Meter<OpCosts> {
@@ -49,8 +106,26 @@ impl Meter<OpCosts> {
         Self {
             costs: pricing.costs,
             header_cost: pricing.ink_header_cost,
+            conversion_cost: pricing.conversion_ink,
+            wide_select_cost: pricing.wide_select_ink,
+            reference_cost: pricing.reference_ink,
+            table_access_cost: pricing.table_access_ink,
+            opcode_pricing: Arc::new(pricing.opcode_pricing.clone()),
+            local_cost: pricing.local_ink,
+            return_value_cost: pricing.return_value_ink,
+            results: RwLock::default(),
+            import_costs: pricing.import_ink.clone(),
+            unmetered: pricing.unmetered_funcs.clone(),
+            max_checks: pricing.max_ink_checks,
+            checks: Arc::default(),
+            granularity: pricing.granularity,
+            imports: RwLock::default(),
+            function_costs: Arc::default(),
+            start: RwLock::default(),
+            start_ink: pricing.start_ink,
             globals: RwLock::default(),
             sigs: RwLock::default(),
+            call_costs: RwLock::default(),
         }
     }
 }
@@ -59,6 +134,26 @@ impl<F: OpcodePricer> Meter<F> {
     pub fn globals(&self) -> [GlobalIndex; 2] {
         self.globals.read().expect("missing globals")
     }
+
+    /// The sum of the ink costs of the operators in each function's body, excluding ink checks.
+    /// Loops and indirect calls are counted once, so this is a lower bound on the ink a single
+    /// call spends, useful for finding the expensive functions of a contract.
+    pub fn function_costs(&self) -> HashMap<FunctionIndex, u64> {
+        self.function_costs.read().clone()
+    }
+
+    /// The static ink cost of the start function, which runs during instantiation.
+    /// Like [`Self::function_costs`], loops are counted once, so this is exact for straight-line
+    /// bodies and a lower bound otherwise.
+    pub fn start_function_cost(&self) -> Result<Option<u64>> {
+        let Some(start) = *self.start.read() else {
+            return Ok(None);
+        };
+        let Some(&cost) = self.function_costs.read().get(&start) else {
+            bail!("start func {} hasn't been metered", start.as_u32().red())
+        };
+        Ok(Some(cost))
+    }
 }
 
 impl<M, F> Middleware<M> for Meter<F>
@@ -69,30 +164,78 @@ where
     type FM<'a> = FuncMeter<'a, F>;
 
     fn update_module(&self, module: &mut M) -> Result<()> {
+        let start_ink = GlobalInit::I64Const(self.start_ink as i64);
         let start_status = GlobalInit::I32Const(0);
-        let ink = module.add_global(STYLUS_INK_LEFT, Type::I64, GlobalInit::I64Const(0))?;
+        let ink = module.add_global(STYLUS_INK_LEFT, Type::I64, start_ink)?;
         let status = module.add_global(STYLUS_INK_STATUS, Type::I32, start_status)?;
         *self.globals.write() = Some([ink, status]);
         *self.sigs.write() = Some(Arc::new(module.all_signatures()?));
+        *self.imports.write() = Some(module.func_import_count());
+
+        let mut results = HashMap::default();
+        if self.return_value_cost > 0 {
+            for (func, ty) in module.all_functions()? {
+                results.insert(func, ty.outputs.len() as u64);
+            }
+        }
+        *self.results.write() = results;
+
+        let mut call_costs = HashMap::default();
+        for ((import, name), &cost) in &self.import_costs {
+            if let Ok(ImportIndex::Function(func)) = module.get_import(import, name) {
+                call_costs.insert(func.as_u32(), cost);
+            }
+        }
+        *self.call_costs.write() = Some(Arc::new(call_costs));
+        self.checks.store(0, Ordering::Relaxed);
+        self.function_costs.write().clear();
+        *self.start.write() = module.start_function();
         Ok(())
     }
 
-    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
         let [ink, status] = self.globals();
-        let sigs = self.sigs.read();
-        let sigs = sigs.as_ref().expect("no types");
-        Ok(FuncMeter::new(
-            ink,
-            status,
-            self.costs.clone(),
-            self.header_cost,
-            sigs.clone(),
-        ))
+        let sigs = self.sigs.read().clone().expect("no types");
+        let call_costs = self.call_costs.read().clone().expect("no call costs");
+        let imports = self.imports.read().expect("no imports");
+        let index = FunctionIndex::from_u32(imports + func.as_u32());
+        let results = self.results.read().get(&index).copied().unwrap_or_default();
+        Ok(FuncMeter {
+            ink_global: ink,
+            status_global: status,
+            block: vec![],
+            block_cost: 0,
+            header_cost: self.header_cost,
+            conversion_cost: self.conversion_cost,
+            wide_select_cost: self.wide_select_cost,
+            reference_cost: self.reference_cost,
+            table_access_cost: self.table_access_cost,
+            opcode_pricing: self.opcode_pricing.clone(),
+            local_cost: self.local_cost,
+            return_cost: self.return_value_cost.saturating_mul(results),
+            depth: 0,
+            costs: self.costs.clone(),
+            sigs,
+            call_costs,
+            unmetered: self.unmetered.contains(&func.as_u32()).then_some(func),
+            max_checks: self.max_checks,
+            checks: self.checks.clone(),
+            granularity: self.granularity,
+            imports,
+            func: index,
+            static_cost: 0,
+            function_costs: self.function_costs.clone(),
+            fresh: true,
+        })
     }
 
     fn name(&self) -> &'static str {
         "ink meter"
     }
+
+    fn metrics(&self, sink: &mut dyn FnMut(&str, u64)) {
+        sink("ink checks", self.checks.load(Ordering::Relaxed));
+    }
 }
 
 #[derive(Derivative)]
@@ -108,48 +251,152 @@ pub struct FuncMeter<'a, F: OpcodePricer> {
     block_cost: u64,
     /// Cost of checking the amount of ink left.
     header_cost: u64,
+    /// Cost of converting between an int and a float, overriding `costs` when set.
+    conversion_cost: Option<u64>,
+    /// Cost of a typed `select` on 64-bit operands, overriding `costs` when set.
+    wide_select_cost: Option<u64>,
+    /// Cost of `ref.func` and `ref.null`, overriding `costs` when set.
+    reference_cost: Option<u64>,
+    /// Cost of `table.get` and `table.set`, overriding `costs` when set.
+    table_access_cost: Option<u64>,
+    /// Costs of whole categories of operators, overriding `costs` when set.
+    opcode_pricing: Arc<OpcodePricing>,
+    /// Per-local cost of setting up a function's frame.
+    local_cost: u64,
+    /// Cost of returning the function's results.
+    return_cost: u64,
+    /// The number of blocks the current operator is nested in.
+    depth: u32,
     /// Associates opcodes to their ink costs.
     #[derivative(Debug = "ignore")]
     costs: F,
     /// The types of the module being instrumented.
     sigs: Arc<SigMap>,
+    /// Surcharges for calling imports, keyed by function index.
+    call_costs: Arc<HashMap<u32, u64>>,
+    /// The function being instrumented, if exempt from metering.
+    unmetered: Option<LocalFunctionIndex>,
+    /// The most ink checks that may be injected into the module, if limited.
+    max_checks: Option<u64>,
+    /// The number of ink checks injected into the module so far.
+    checks: Arc<AtomicU64>,
+    /// Where ink checks are injected.
+    granularity: MeterGranularity,
+    /// The number of imported functions, which precede local ones.
+    imports: u32,
+    /// The function being instrumented.
+    func: FunctionIndex,
+    /// The accumulated cost of the function's operators.
+    static_cost: u64,
+    /// The static ink cost of each function instrumented so far.
+    function_costs: Arc<RwLock<HashMap<FunctionIndex, u64>>>,
+    /// Whether no operators have been fed yet.
+    fresh: bool,
 }
 
-impl<'a, F: OpcodePricer> FuncMeter<'a, F> {
-    fn new(
-        ink_global: GlobalIndex,
-        status_global: GlobalIndex,
-        costs: F,
-        header_cost: u64,
-        sigs: Arc<SigMap>,
-    ) -> Self {
-        Self {
-            ink_global,
-            status_global,
-            block: vec![],
-            block_cost: 0,
-            header_cost,
-            costs,
-            sigs,
+impl<'a, F: OpcodePricer> FuncMiddleware<'a> for FuncMeter<'a, F> {
+    fn locals_info(&mut self, locals: &[ValType]) {
+        if self.granularity == MeterGranularity::HostCallsOnly {
+            return; // setting up a frame is pure compute
         }
+
+        // the frame is set up on entry, so charge for it in the first basic block
+        let frame_cost = self.local_cost.saturating_mul(locals.len() as u64);
+        self.block_cost = self.block_cost.saturating_add(frame_cost);
     }
-}
 
-impl<'a, F: OpcodePricer> FuncMiddleware<'a> for FuncMeter<'a, F> {
     fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
     where
         O: Extend<Operator<'a>>,
     {
         use Operator::*;
 
+        // exempt funcs may neither loop nor call, tail calls included, so they run in bounded time
+        if let Some(func) = self.unmetered {
+            let calls = matches!(op, Call { .. } | CallIndirect { .. });
+            let tail_calls = matches!(op, ReturnCall { .. } | ReturnCallIndirect { .. });
+            if calls || tail_calls || matches!(op, Loop { .. }) {
+                let func = func.as_u32().red();
+                bail!("unmetered func {func} cannot use {}", op.code().red())
+            }
+            out.extend([op]);
+            return Ok(());
+        }
+
+        // only host calls are charged, each in a block of its own
+        if self.granularity == MeterGranularity::HostCallsOnly {
+            let imports = self.imports;
+            if !matches!(op, Call { function_index } if function_index < imports) {
+                out.extend([op]);
+                return Ok(());
+            }
+        }
+
         let end = op.ends_basic_block();
 
-        let op_cost = (self.costs)(&op, &self.sigs);
+        // untyped selects don't name their operands, so only typed ones are priced by width
+        let wide_select = matches!(
+            op,
+            TypedSelect {
+                ty: ValType::I64 | ValType::F64
+            }
+        );
+        let reference = matches!(op, RefFunc { .. } | RefNull { .. });
+        let table_access = matches!(op, TableGet { .. } | TableSet { .. });
+
+        let overrides = [
+            (self.conversion_cost, op.is_conversion()),
+            (self.wide_select_cost, wide_select),
+            (self.reference_cost, reference),
+            (self.table_access_cost, table_access),
+        ];
+        let overridden = overrides
+            .into_iter()
+            .find_map(|(cost, applies)| cost.filter(|_| applies))
+            .or_else(|| self.opcode_pricing.cost(&op));
+        let mut op_cost = overridden.unwrap_or_else(|| (self.costs)(&op, &self.sigs));
+
+        // calls end the block, so the host's surcharge is paid before entering it
+        if let Call { function_index } = &op {
+            let surcharge = self.call_costs.get(function_index).copied();
+            op_cost = op_cost.saturating_add(surcharge.unwrap_or_default());
+        }
+
+        // results are moved whether returned explicitly or by falling off the final end
+        let returns = match op {
+            Return => true,
+            End => self.depth == 0,
+            _ => false,
+        };
+        match op {
+            Block { .. } | Loop { .. } | If { .. } | Try { .. } => self.depth += 1,
+            End | Delegate { .. } => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+        if returns {
+            op_cost = op_cost.saturating_add(self.return_cost);
+        }
+        self.static_cost = self.static_cost.saturating_add(op_cost);
         let mut cost = self.block_cost.saturating_add(op_cost);
         self.block_cost = cost;
+
+        // an empty body that's free to run, frame and return included, needs no check
+        let trivial = self.fresh && returns && matches!(op, End) && cost == 0;
+        self.fresh = false;
         self.block.push(op);
 
-        if end {
+        if trivial {
+            out.extend(self.block.drain(..));
+            self.function_costs.write().insert(self.func, 0);
+        } else if end {
+            let checks = self.checks.fetch_add(1, Ordering::Relaxed) + 1;
+            if let Some(max) = self.max_checks.filter(|&max| checks > max) {
+                bail!(
+                    "module needs more than {} ink checks, exceeding the limit",
+                    max.red()
+                );
+            }
+
             let ink = self.ink_global.as_u32();
             let status = self.status_global.as_u32();
             let blockty = BlockType::Empty;
@@ -177,6 +424,9 @@ impl<'a, F: OpcodePricer> FuncMiddleware<'a> for FuncMeter<'a, F> {
             ]);
             out.extend(self.block.drain(..));
             self.block_cost = 0;
+
+            let mut costs = self.function_costs.write();
+            costs.insert(self.func, self.static_cost);
         }
         Ok(())
     }
@@ -355,6 +605,44 @@ impl MeteredMachine for Machine {
     }
 }
 
+/// Reads a metered instance's ink without naming its globals, ignoring whether it's run out.
+/// Panics if the instance wasn't instrumented by a [`Meter`].
+#[cfg(feature = "native")]
+pub fn ink_remaining(instance: &Instance, store: &mut Store) -> u64 {
+    let global = instance.exports.get_global(STYLUS_INK_LEFT);
+    let WasmerValue::I64(ink) = global.expect("missing ink global").get(store) else {
+        panic!("ink global has the wrong type")
+    };
+    ink as u64
+}
+
+/// Refills a metered instance's ink, clearing any prior exhaustion.
+/// Panics if the instance wasn't instrumented by a [`Meter`].
+#[cfg(feature = "native")]
+pub fn set_ink_remaining(instance: &mut Instance, store: &mut Store, ink: u64) {
+    let exports = &instance.exports;
+    let ink_left = exports
+        .get_global(STYLUS_INK_LEFT)
+        .expect("missing ink global");
+    let status = exports
+        .get_global(STYLUS_INK_STATUS)
+        .expect("missing status global");
+    ink_left.set(store, WasmerValue::I64(ink as i64)).unwrap();
+    status.set(store, WasmerValue::I32(0)).unwrap();
+}
+
+/// Reads a metered instance's ink, accounting for the status flag set when it runs out.
+/// Panics if the instance wasn't instrumented by a [`Meter`].
+#[cfg(feature = "native")]
+pub fn ink_status(instance: &Instance, store: &mut Store) -> MachineMeter {
+    let global = instance.exports.get_global(STYLUS_INK_STATUS);
+    match global.expect("missing status global").get(store) {
+        WasmerValue::I32(0) => MachineMeter::Ready(ink_remaining(instance, store)),
+        WasmerValue::I32(_) => MachineMeter::Exhausted,
+        _ => panic!("status global has the wrong type"),
+    }
+}
+
 pub fn pricing_v1(op: &Operator, tys: &HashMap<SignatureIndex, FunctionType>) -> u64 {
     use Operator::*;
 
@@ -6,17 +6,17 @@ use crate::{
     machine::Module,
     memory::MemoryType,
     programs::config::CompileConfig,
-    value::{FunctionType as ArbFunctionType, Value},
+    value::{ArbValueType, FunctionType as ArbFunctionType, Value},
 };
-use arbutil::{math::SaturatingSum, Color};
+use arbutil::{crypto, math::SaturatingSum, Color};
 use eyre::{bail, eyre, Report, Result, WrapErr};
-use fnv::FnvHashMap as HashMap;
-use std::fmt::Debug;
+use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
+use std::fmt::{self, Debug, Display, Formatter};
 use wasmer_types::{
     entity::EntityRef, FunctionIndex, GlobalIndex, GlobalInit, ImportIndex, LocalFunctionIndex,
-    SignatureIndex, Type,
+    Pages, SignatureIndex, Type, WASM_MAX_PAGES, WASM_PAGE_SIZE,
 };
-use wasmparser::{Operator, ValType};
+use wasmparser::{Data, DataKind, ElementKind, Operator, ValType};
 
 #[cfg(feature = "native")]
 use {
@@ -25,31 +25,309 @@ use {
     wasmer::{
         ExportIndex, FunctionMiddleware, GlobalType, MiddlewareError, ModuleMiddleware, Mutability,
     },
-    wasmer_types::{MemoryIndex, ModuleInfo},
+    wasmer_types::{MemoryIndex, ModuleInfo, TableIndex},
 };
 
+pub mod align;
+pub mod analysis;
+pub mod balance;
 pub mod config;
+pub mod constant;
 pub mod counter;
+pub mod data;
 pub mod depth;
+pub mod diff;
+pub mod drop;
 pub mod dynamic;
+pub mod elements;
+pub mod exception;
+pub mod exports;
+pub mod features;
+pub mod global;
 pub mod heap;
+pub mod imports;
+pub mod limits;
+pub mod locals;
 pub mod memory;
 pub mod meter;
+pub mod nonfloat;
 pub mod prelude;
+pub mod profile;
+pub mod prologue;
+pub mod random;
+pub mod registry;
+pub mod rewrite;
+pub mod single_memory;
+pub mod single_table;
+pub mod size;
 pub mod start;
+pub mod strip;
+pub mod summary;
+pub mod view;
+pub mod watchdog;
 
 pub const STYLUS_ENTRY_POINT: &str = "user_entrypoint";
 
+/// How `add_global` behaves when the requested name is already exported.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnGlobalCollision {
+    /// Fail with an error.
+    #[default]
+    Error,
+    /// Return the existing global, provided it has the same type.
+    Reuse,
+    /// Append `_1`, `_2`, etc. until the name is unique.
+    Rename,
+}
+
+/// Hashes a module's interface, given a description of each import and export.
+fn hash_abi(mut entries: Vec<String>) -> [u8; 32] {
+    entries.sort();
+    crypto::keccak(entries.join("\n"))
+}
+
+impl OnGlobalCollision {
+    fn unique_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+        (1..)
+            .map(|suffix| format!("{name}_{suffix}"))
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+}
+
+/// The ways querying or modifying a module may fail, for callers that need to tell them apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModuleModError {
+    /// The module already exports the name
+    DuplicateExport(String),
+    /// The export exists but isn't a global
+    NotAGlobal(String),
+    /// The global exists but has a different type
+    GlobalTypeMismatch(String),
+    /// Globals can't be initialized this way
+    UnsupportedGlobalInit(String),
+    /// No global is exported under the name
+    MissingGlobal(String),
+    /// Nothing is exported under the name
+    MissingExport(String),
+    /// The type section has no signature at the index
+    MissingSignature(SignatureIndex),
+    /// The signature has types the prover doesn't support
+    UnsupportedSignature(SignatureIndex, String),
+    /// The module has no function at the index, though it may still have a name for it
+    MissingFunction(FunctionIndex, Option<String>),
+    /// The start function is imported rather than defined by the module
+    ImportedStart(FunctionIndex),
+    /// The start function takes or returns values, which the spec forbids
+    NonEmptyStart(FunctionIndex, ArbFunctionType),
+    /// No memory is exported under the name
+    MissingMemory(String),
+    /// The module has more than one memory, which isn't allowed
+    MultiMemoryUnsupported,
+    /// The memory's bounds don't fit in a page count
+    UnsupportedMemory(String),
+    /// The memory starts larger than its bound allows
+    MemoryMinimumExceedsLimit {
+        memory: u32,
+        minimum: Pages,
+        limit: Pages,
+    },
+}
+
+impl Display for ModuleModError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use ModuleModError::*;
+        match self {
+            DuplicateExport(name) => write!(f, "wasm already contains {}", name.red()),
+            NotAGlobal(name) => write!(f, "cannot reuse non-global export {}", name.red()),
+            GlobalTypeMismatch(name) => {
+                write!(f, "cannot reuse global {} of a different type", name.red())
+            }
+            UnsupportedGlobalInit(init) => write!(f, "cannot add global of type {init}"),
+            MissingGlobal(name) => write!(f, "missing global {}", name.red()),
+            MissingExport(name) => write!(f, "missing export {}", name.red()),
+            MissingSignature(sig) => write!(f, "missing signature {}", sig.as_u32().red()),
+            UnsupportedSignature(sig, error) => {
+                write!(f, "unsupported signature {}: {error}", sig.as_u32().red())
+            }
+            MissingFunction(func, Some(name)) => {
+                let index = func.as_u32().red();
+                write!(f, "missing func {} @ index {index}", name.red())
+            }
+            MissingFunction(func, None) => {
+                write!(f, "missing func @ index {}", func.as_u32().red())
+            }
+            ImportedStart(func) => write!(f, "start function {} is an import", func.as_u32().red()),
+            NonEmptyStart(func, ty) => {
+                let func = func.as_u32().red();
+                write!(f, "start function {func} has non-empty signature {ty}")
+            }
+            MissingMemory(name) => write!(f, "missing memory with export name {}", name.red()),
+            MultiMemoryUnsupported => write!(f, "only one memory is allowed"),
+            UnsupportedMemory(error) => write!(f, "unsupported memory: {error}"),
+            MemoryMinimumExceedsLimit {
+                memory,
+                minimum,
+                limit,
+            } => {
+                let (minimum, limit) = (minimum.0.red(), limit.0.red());
+                write!(
+                    f,
+                    "memory {} size {minimum} exceeds bound {limit}",
+                    memory.red()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModuleModError {}
+
 pub trait ModuleMod {
-    fn add_global(&mut self, name: &str, ty: Type, init: GlobalInit) -> Result<GlobalIndex>;
-    fn get_global(&mut self, name: &str) -> Result<GlobalIndex>;
-    fn get_signature(&self, sig: SignatureIndex) -> Result<ArbFunctionType>;
-    fn get_function(&self, func: FunctionIndex) -> Result<ArbFunctionType>;
+    fn add_global(
+        &mut self,
+        name: &str,
+        ty: Type,
+        init: GlobalInit,
+    ) -> Result<GlobalIndex, ModuleModError> {
+        let collision = OnGlobalCollision::Error;
+        Ok(self.add_global_as(name, ty, init, collision)?.1)
+    }
+    fn add_global_as(
+        &mut self,
+        name: &str,
+        ty: Type,
+        init: GlobalInit,
+        collision: OnGlobalCollision,
+    ) -> Result<(String, GlobalIndex), ModuleModError>;
+    fn get_global(&mut self, name: &str) -> Result<GlobalIndex, ModuleModError>;
+    /// Removes the global exported under the given name, along with its export.
+    /// Removing a global would renumber those after it, so it's only dropped when last, unused,
+    /// and not exported under another name.
+    /// Otherwise, including always for [`ModuleInfo`], it lingers unexported, leaving a gap.
+    fn remove_global(&mut self, name: &str) -> Result<(), ModuleModError>;
+    /// Exports under a new name whatever the old name exported, failing if the new one is taken.
+    fn rename_export(&mut self, old: &str, new: &str) -> Result<(), ModuleModError>;
+    fn get_signature(&self, sig: SignatureIndex) -> Result<ArbFunctionType, ModuleModError>;
+    fn get_function(&self, func: FunctionIndex) -> Result<ArbFunctionType, ModuleModError>;
     fn all_functions(&self) -> Result<HashMap<FunctionIndex, ArbFunctionType>>;
     fn all_signatures(&self) -> Result<HashMap<SignatureIndex, ArbFunctionType>>;
     fn get_import(&self, module: &str, name: &str) -> Result<ImportIndex>;
-    fn move_start_function(&mut self, name: &str) -> Result<()>;
-    fn memory_info(&self) -> Result<MemoryType>;
+    /// The module and name of each imported function, along with its index.
+    fn func_imports(&self) -> Vec<(String, String, FunctionIndex)>;
+    fn move_start_function(&mut self, name: &str) -> Result<(), ModuleModError> {
+        self.move_start_function_forced(name, false).map(|_| ())
+    }
+    /// Like `move_start_function`, but overwrites any existing export of the name when forced.
+    /// Returns the kind of the export overwritten, if any.
+    fn move_start_function_forced(
+        &mut self,
+        name: &str,
+        force: bool,
+    ) -> Result<Option<ExportKind>, ModuleModError>;
+    fn set_start_function(&mut self, func: FunctionIndex) -> Result<(), ModuleModError>;
+    fn clear_start_function(&mut self);
+    fn start_function(&self) -> Option<FunctionIndex>;
+    /// The type of the start function, if the module has one.
+    fn start_function_type(&self) -> Result<Option<ArbFunctionType>>;
+    fn memory_info(&self) -> Result<MemoryType, ModuleModError>;
+    /// Exports the module's lone memory under the given name if it isn't already.
+    fn ensure_memory_export(&mut self, name: &str) -> Result<()>;
+    fn memory_minimum(&self, index: u32) -> Result<Pages>;
+    fn memory_maximum(&self, index: u32) -> Result<Option<Pages>>;
+    fn memory_count(&self) -> u32;
+    /// Clamps each memory's maximum to the given limit, returning the bound each ends up with.
+    /// A memory without a declared maximum, or with one above the limit, gets the limit instead.
+    fn limit_heap(&mut self, limit: Pages) -> Result<Vec<Pages>, ModuleModError>;
+    /// The most entries the table at the given index may grow to, or `None` if unbounded.
+    fn table_maximum(&self, index: u32) -> Result<Option<u32>>;
+    fn table_count(&self) -> u32;
+    /// The type of the references held by the table at the given index.
+    fn table_element_type(&self, index: u32) -> Result<ValType>;
+    fn global_count(&self) -> u32;
+    fn global_type(&self, index: u32) -> Result<ArbValueType>;
+    fn func_import_count(&self) -> u32;
+    fn export_count(&self) -> u32;
+    /// The kind of the export with the given name, if one exists.
+    fn export_kind(&self, name: &str) -> Option<ExportKind>;
+    /// Whether the function is exported under at least one name.
+    fn is_function_exported(&self, func: FunctionIndex) -> bool;
+    /// The number of functions defined by the module itself, excluding imports.
+    fn num_defined_functions(&self) -> usize;
+    /// The total number of entries across the module's passive element segments.
+    fn passive_element_count(&self) -> usize;
+    /// Whether the module declares any passive data segments, which `memory.init` copies from.
+    fn has_passive_data(&self) -> bool;
+    /// The number of passive data segments.
+    /// Active ones aren't counted, since [`ModuleInfo`] doesn't retain them.
+    fn passive_data_count(&self) -> usize;
+    /// The total size in bytes of the module's passive data segments.
+    /// Active ones aren't counted, since [`ModuleInfo`] doesn't retain them.
+    fn passive_data_bytes(&self) -> usize;
+    /// Whether the module relies on the reference-types proposal, via reference-typed locals,
+    /// globals, or signatures, `externref` tables, or operators like `ref.func` and `table.get`.
+    /// Since `funcref` tables predate the proposal, they alone don't count.
+    /// Function bodies aren't available to [`ModuleInfo`], so there only declarations are checked.
+    fn uses_reference_types(&self) -> bool;
+    fn abi_fingerprint(&self) -> [u8; 32];
+
+    /// The number of functions in the module, including imports.
+    fn function_count(&self) -> usize {
+        self.func_import_count() as usize + self.num_defined_functions()
+    }
+
+    /// Iterates over the type of every function in index order, imports first.
+    /// Unlike [`Self::all_functions`], an unsupported signature doesn't end the iteration.
+    fn functions(&self) -> Box<dyn Iterator<Item = (FunctionIndex, Result<ArbFunctionType>)> + '_> {
+        let funcs = (0..self.function_count() as u32).map(FunctionIndex::from_u32);
+        Box::new(funcs.map(|func| (func, self.get_function(func).map_err(Report::new))))
+    }
+
+    /// Ensures the module exports each of the given names with the given kind, reporting every
+    /// missing or mistyped export at once.
+    fn check_required_exports(&self, names: &[(&str, ExportKind)]) -> Result<()> {
+        let mut problems = vec![];
+        for &(name, kind) in names {
+            let found = self.export_kind(name);
+            let name = name.red();
+            match found {
+                Some(actual) if actual == kind => {}
+                Some(actual) => problems.push(format!("{name} is a {actual:?}, not a {kind:?}")),
+                None => problems.push(format!("{name} {kind:?} is missing")),
+            }
+        }
+        if !problems.is_empty() {
+            bail!("required exports invalid: {}", problems.join(", "))
+        }
+        Ok(())
+    }
+
+    /// The number of structurally distinct signatures in the type section.
+    fn distinct_signature_count(&self) -> Result<usize> {
+        let sigs = self.all_signatures()?;
+        Ok(sigs.into_values().collect::<HashSet<_>>().len())
+    }
+
+    /// The most memory the module's heap may grow to in bytes, or `None` if unbounded.
+    /// Fails if the module lacks the lone, exported memory [`Self::memory_info`] expects.
+    fn heap_limit_bytes(&self) -> Result<Option<u64>> {
+        let Some(max) = self.memory_info()?.max else {
+            return Ok(None);
+        };
+        let max = max.min(Pages(WASM_MAX_PAGES));
+        Ok(Some(max.0 as u64 * WASM_PAGE_SIZE as u64))
+    }
+
+    /// How many more pages the module's heap may grow by, or `None` if unbounded.
+    /// Fails if the module lacks the lone, exported memory [`Self::memory_info`] expects.
+    fn growable_pages(&self) -> Result<Option<Pages>> {
+        let memory = self.memory_info()?;
+        let Some(max) = memory.max else {
+            return Ok(None);
+        };
+        let max = max.min(Pages(WASM_MAX_PAGES));
+        Ok(Some(Pages(max.0.saturating_sub(memory.min.0))))
+    }
 }
 
 pub trait Middleware<M: ModuleMod> {
@@ -58,6 +336,9 @@ pub trait Middleware<M: ModuleMod> {
     fn update_module(&self, module: &mut M) -> Result<()>; // not mutable due to wasmer
     fn instrument<'a>(&self, func_index: LocalFunctionIndex) -> Result<Self::FM<'a>>;
     fn name(&self) -> &'static str;
+
+    /// Reports counts gathered while instrumenting, like the number of checks injected.
+    fn metrics(&self, _sink: &mut dyn FnMut(&str, u64)) {}
 }
 
 pub trait FuncMiddleware<'a> {
@@ -69,6 +350,12 @@ pub trait FuncMiddleware<'a> {
     where
         O: Extend<Operator<'a>>;
 
+    /// Called once the function's last operator has been fed.
+    /// Only the prover calls this, since wasmer's `FunctionMiddleware` has no equivalent.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     /// The name of the middleware
     fn name(&self) -> &'static str;
 }
@@ -90,6 +377,24 @@ impl<'a> FuncMiddleware<'a> for DefaultFuncMiddleware {
     }
 }
 
+/// Holds a problem found in `locals_info`, which can't fail, until `feed` can report it.
+/// Every function has at least its final `end`, so the error always surfaces.
+#[derive(Debug, Default)]
+pub struct LocalsError(Option<String>);
+
+impl LocalsError {
+    pub fn set(&mut self, error: String) {
+        self.0 = Some(error);
+    }
+
+    pub fn check(&self) -> Result<()> {
+        match &self.0 {
+            Some(error) => bail!("{error}"),
+            None => Ok(()),
+        }
+    }
+}
+
 /// This wrapper exists to impl wasmer's `ModuleMiddleware` generically.
 /// We can't use `T` directly since we don't define `ModuleMiddleware`,
 /// and we need `M` to be part of the type.
@@ -161,40 +466,81 @@ where
 
 #[cfg(feature = "native")]
 impl ModuleMod for ModuleInfo {
-    fn add_global(&mut self, name: &str, ty: Type, init: GlobalInit) -> Result<GlobalIndex> {
-        let global_type = GlobalType::new(ty, Mutability::Var);
-        let name = name.to_owned();
-        if self.exports.contains_key(&name) {
-            bail!("wasm already contains {}", name.red())
+    fn add_global_as(
+        &mut self,
+        name: &str,
+        ty: Type,
+        init: GlobalInit,
+        collision: OnGlobalCollision,
+    ) -> Result<(String, GlobalIndex), ModuleModError> {
+        let mut name = name.to_owned();
+        if let Some(prior) = self.exports.get(&name).cloned() {
+            match collision {
+                OnGlobalCollision::Error => return Err(ModuleModError::DuplicateExport(name)),
+                OnGlobalCollision::Reuse => {
+                    let ExportIndex::Global(global) = prior else {
+                        return Err(ModuleModError::NotAGlobal(name));
+                    };
+                    if self.globals[global].ty != ty {
+                        return Err(ModuleModError::GlobalTypeMismatch(name));
+                    }
+                    return Ok((name, global));
+                }
+                OnGlobalCollision::Rename => {
+                    name = OnGlobalCollision::unique_name(&name, |x| self.exports.contains_key(x));
+                }
+            }
         }
+        let global_type = GlobalType::new(ty, Mutability::Var);
         let index = self.globals.push(global_type);
-        self.exports.insert(name, ExportIndex::Global(index));
+        self.exports
+            .insert(name.clone(), ExportIndex::Global(index));
         self.global_initializers.push(init);
-        Ok(index)
+        Ok((name, index))
     }
 
-    fn get_global(&mut self, name: &str) -> Result<GlobalIndex> {
+    fn get_global(&mut self, name: &str) -> Result<GlobalIndex, ModuleModError> {
         let Some(ExportIndex::Global(global)) = self.exports.get(name) else {
-            bail!("missing global {}", name.red())
+            return Err(ModuleModError::MissingGlobal(name.to_owned()));
         };
         Ok(*global)
     }
 
-    fn get_signature(&self, sig: SignatureIndex) -> Result<ArbFunctionType> {
-        let error = Report::msg(format!("missing signature {}", sig.as_u32().red()));
+    fn remove_global(&mut self, name: &str) -> Result<(), ModuleModError> {
+        let Some(ExportIndex::Global(_)) = self.exports.get(name) else {
+            return Err(ModuleModError::MissingGlobal(name.to_owned()));
+        };
+        // wasmer's index maps can't shrink, so the global itself remains
+        self.exports.shift_remove(name);
+        Ok(())
+    }
+
+    fn rename_export(&mut self, old: &str, new: &str) -> Result<(), ModuleModError> {
+        if self.exports.contains_key(new) {
+            return Err(ModuleModError::DuplicateExport(new.to_owned()));
+        }
+        let Some(export) = self.exports.shift_remove(old) else {
+            return Err(ModuleModError::MissingExport(old.to_owned()));
+        };
+        self.exports.insert(new.to_owned(), export);
+        Ok(())
+    }
+
+    fn get_signature(&self, sig: SignatureIndex) -> Result<ArbFunctionType, ModuleModError> {
+        let error = ModuleModError::MissingSignature(sig);
         let ty = self.signatures.get(sig).cloned().ok_or(error)?;
         let ty = value::parser_func_type(ty);
-        ty.try_into()
+        let error = |err: Report| ModuleModError::UnsupportedSignature(sig, err.to_string());
+        ty.try_into().map_err(error)
     }
 
-    fn get_function(&self, func: FunctionIndex) -> Result<ArbFunctionType> {
-        let index = func.as_u32();
+    fn get_function(&self, func: FunctionIndex) -> Result<ArbFunctionType, ModuleModError> {
         match self.functions.get(func) {
             Some(sig) => self.get_signature(*sig),
-            None => match self.function_names.get(&func) {
-                Some(name) => bail!("missing func {} @ index {}", name.red(), index.red()),
-                None => bail!("missing func @ index {}", index.red()),
-            },
+            None => {
+                let name = self.function_names.get(&func).cloned();
+                Err(ModuleModError::MissingFunction(func, name))
+            }
         }
     }
 
@@ -224,9 +570,37 @@ impl ModuleMod for ModuleInfo {
             .ok_or_else(|| eyre!("missing import {}", name.red()))
     }
 
-    fn move_start_function(&mut self, name: &str) -> Result<()> {
-        if let Some(prior) = self.exports.get(name) {
-            bail!("function {} already exists @ index {:?}", name.red(), prior)
+    fn func_imports(&self) -> Vec<(String, String, FunctionIndex)> {
+        let imports = self.imports.iter();
+        let funcs = imports.filter_map(|(key, index)| match index {
+            ImportIndex::Function(func) => Some((key.module.clone(), key.field.clone(), *func)),
+            _ => None,
+        });
+        funcs.collect()
+    }
+
+    fn move_start_function_forced(
+        &mut self,
+        name: &str,
+        force: bool,
+    ) -> Result<Option<ExportKind>, ModuleModError> {
+        if let Some(start) = self.start_function {
+            if self.is_imported_function(start) {
+                return Err(ModuleModError::ImportedStart(start));
+            }
+            let ty = self.get_function(start)?;
+            if !ty.inputs.is_empty() || !ty.outputs.is_empty() {
+                return Err(ModuleModError::NonEmptyStart(start, ty));
+            }
+        }
+        let mut replaced = None;
+        if let Some(prior) = self.export_kind(name) {
+            if !force {
+                return Err(ModuleModError::DuplicateExport(name.to_owned()));
+            }
+            if self.start_function.is_some() {
+                replaced = Some(prior);
+            }
         }
 
         if let Some(start) = self.start_function.take() {
@@ -234,56 +608,319 @@ impl ModuleMod for ModuleInfo {
             self.exports.insert(name.to_owned(), export);
             self.function_names.insert(start, name.to_owned());
         }
+        Ok(replaced)
+    }
+
+    fn set_start_function(&mut self, func: FunctionIndex) -> Result<(), ModuleModError> {
+        if self.is_imported_function(func) {
+            return Err(ModuleModError::ImportedStart(func));
+        }
+        let ty = self.get_function(func)?;
+        if !ty.inputs.is_empty() || !ty.outputs.is_empty() {
+            return Err(ModuleModError::NonEmptyStart(func, ty));
+        }
+        self.start_function = Some(func);
         Ok(())
     }
 
-    fn memory_info(&self) -> Result<MemoryType> {
+    fn clear_start_function(&mut self) {
+        self.start_function = None;
+    }
+
+    fn start_function(&self) -> Option<FunctionIndex> {
+        self.start_function
+    }
+
+    fn start_function_type(&self) -> Result<Option<ArbFunctionType>> {
+        let Some(start) = self.start_function else {
+            return Ok(None);
+        };
+        Ok(Some(self.get_function(start)?))
+    }
+
+    fn memory_info(&self) -> Result<MemoryType, ModuleModError> {
+        let missing = || ModuleModError::MissingMemory("memory".to_owned());
         if self.memories.is_empty() {
-            bail!("missing memory export with name {}", "memory".red());
+            return Err(missing());
         }
         if self.memories.len() > 1 {
-            bail!("only one memory is allowed");
+            return Err(ModuleModError::MultiMemoryUnsupported);
         }
         if self.exports.get("memory") != Some(&ExportIndex::Memory(MemoryIndex::from_u32(0))) {
-            bail!("missing memory with export name {}", "memory".red());
+            return Err(missing());
         }
         Ok(self.memories.last().unwrap().into())
     }
+
+    fn ensure_memory_export(&mut self, name: &str) -> Result<()> {
+        if let Some(export) = self.exports.get(name) {
+            let ExportIndex::Memory(_) = export else {
+                bail!("export {} is not a memory", name.red())
+            };
+            return Ok(());
+        }
+        match self.memories.len() {
+            0 => bail!("cannot export {} without a memory", name.red()),
+            1 => {
+                let memory = ExportIndex::Memory(MemoryIndex::from_u32(0));
+                self.exports.insert(name.to_owned(), memory);
+                Ok(())
+            }
+            count => bail!("cannot choose which of {} memories to export", count.red()),
+        }
+    }
+
+    fn memory_minimum(&self, index: u32) -> Result<Pages> {
+        match self.memories.get(MemoryIndex::from_u32(index)) {
+            Some(memory) => Ok(memory.minimum),
+            None => bail!("missing memory @ index {}", index.red()),
+        }
+    }
+
+    fn memory_maximum(&self, index: u32) -> Result<Option<Pages>> {
+        match self.memories.get(MemoryIndex::from_u32(index)) {
+            Some(memory) => Ok(memory.maximum),
+            None => bail!("missing memory @ index {}", index.red()),
+        }
+    }
+
+    fn memory_count(&self) -> u32 {
+        self.memories.len() as u32
+    }
+
+    fn limit_heap(&mut self, limit: Pages) -> Result<Vec<Pages>, ModuleModError> {
+        // check every memory before clamping any, so that failing leaves the module as it was
+        for (index, memory) in self.memories.iter() {
+            if memory.minimum > limit {
+                return Err(ModuleModError::MemoryMinimumExceedsLimit {
+                    memory: index.as_u32(),
+                    minimum: memory.minimum,
+                    limit,
+                });
+            }
+        }
+        let mut bounds = Vec::with_capacity(self.memories.len());
+        for (_, memory) in self.memories.iter_mut() {
+            let bound = memory.maximum.map_or(limit, |max| max.min(limit));
+            memory.maximum = Some(bound);
+            bounds.push(bound);
+        }
+        Ok(bounds)
+    }
+
+    fn table_maximum(&self, index: u32) -> Result<Option<u32>> {
+        match self.tables.get(TableIndex::from_u32(index)) {
+            Some(table) => Ok(table.maximum),
+            None => bail!("missing table @ index {}", index.red()),
+        }
+    }
+
+    fn table_count(&self) -> u32 {
+        self.tables.len() as u32
+    }
+
+    fn table_element_type(&self, index: u32) -> Result<ValType> {
+        match self.tables.get(TableIndex::from_u32(index)) {
+            Some(table) => Ok(value::parser_type(&table.ty)),
+            None => bail!("missing table @ index {}", index.red()),
+        }
+    }
+
+    fn global_count(&self) -> u32 {
+        self.globals.len() as u32
+    }
+
+    fn global_type(&self, index: u32) -> Result<ArbValueType> {
+        let Some(global) = self.globals.get(GlobalIndex::from_u32(index)) else {
+            bail!("missing global @ index {}", index.red())
+        };
+        value::parser_type(&global.ty).try_into()
+    }
+
+    fn func_import_count(&self) -> u32 {
+        self.num_imported_functions as u32
+    }
+
+    fn export_count(&self) -> u32 {
+        self.exports.len() as u32
+    }
+
+    fn export_kind(&self, name: &str) -> Option<ExportKind> {
+        let kind = match self.exports.get(name)? {
+            ExportIndex::Function(_) => ExportKind::Func,
+            ExportIndex::Table(_) => ExportKind::Table,
+            ExportIndex::Memory(_) => ExportKind::Memory,
+            ExportIndex::Global(_) => ExportKind::Global,
+        };
+        Some(kind)
+    }
+
+    fn is_function_exported(&self, func: FunctionIndex) -> bool {
+        let func = ExportIndex::Function(func);
+        self.exports.values().any(|export| *export == func)
+    }
+
+    fn num_defined_functions(&self) -> usize {
+        self.functions.len() - self.num_imported_functions
+    }
+
+    fn passive_element_count(&self) -> usize {
+        self.passive_elements.values().map(|elem| elem.len()).sum()
+    }
+
+    fn has_passive_data(&self) -> bool {
+        !self.passive_data.is_empty()
+    }
+
+    fn passive_data_count(&self) -> usize {
+        self.passive_data.len()
+    }
+
+    fn passive_data_bytes(&self) -> usize {
+        self.passive_data.values().map(|data| data.len()).sum()
+    }
+
+    fn uses_reference_types(&self) -> bool {
+        let is_ref = |ty: &Type| matches!(ty, Type::FuncRef | Type::ExternRef);
+        let sigs = self.signatures.values();
+        let mut sigs = sigs.flat_map(|sig| sig.params().iter().chain(sig.results()));
+        let mut tables = self.tables.values();
+
+        self.globals.values().any(|global| is_ref(&global.ty))
+            || tables.any(|table| table.ty == Type::ExternRef)
+            || sigs.any(is_ref)
+    }
+
+    fn abi_fingerprint(&self) -> [u8; 32] {
+        let func = |func| match self.get_function(func) {
+            Ok(ty) => format!("func {ty}"),
+            Err(_) => "func ?".to_owned(),
+        };
+        let global = |global| {
+            let ty = self.globals.get(global).map(|x| value::parser_type(&x.ty));
+            match ty.map(value::ArbValueType::try_from) {
+                Some(Ok(ty)) => format!("global {ty}"),
+                _ => "global ?".to_owned(),
+            }
+        };
+
+        let mut entries = vec![];
+        for (key, index) in &self.imports {
+            let desc = match index {
+                ImportIndex::Function(index) => func(*index),
+                ImportIndex::Global(index) => global(*index),
+                ImportIndex::Memory(_) => "memory".to_owned(),
+                ImportIndex::Table(_) => "table".to_owned(),
+            };
+            entries.push(format!("import {} {}: {desc}", key.module, key.field));
+        }
+        for (name, index) in &self.exports {
+            let desc = match index {
+                ExportIndex::Function(index) => func(*index),
+                ExportIndex::Global(index) => global(*index),
+                ExportIndex::Memory(_) => "memory".to_owned(),
+                ExportIndex::Table(_) => "table".to_owned(),
+            };
+            entries.push(format!("export {name}: {desc}"));
+        }
+        hash_abi(entries)
+    }
 }
 
 impl<'a> ModuleMod for WasmBinary<'a> {
-    fn add_global(&mut self, name: &str, _ty: Type, init: GlobalInit) -> Result<GlobalIndex> {
+    fn add_global_as(
+        &mut self,
+        name: &str,
+        _ty: Type,
+        init: GlobalInit,
+        collision: OnGlobalCollision,
+    ) -> Result<(String, GlobalIndex), ModuleModError> {
         let global = match init {
             GlobalInit::I32Const(x) => Value::I32(x as u32),
             GlobalInit::I64Const(x) => Value::I64(x as u64),
             GlobalInit::F32Const(x) => Value::F32(x),
             GlobalInit::F64Const(x) => Value::F64(x),
-            ty => bail!("cannot add global of type {:?}", ty),
+            ty => return Err(ModuleModError::UnsupportedGlobalInit(format!("{ty:?}"))),
         };
-        if self.exports.contains_key(name) {
-            bail!("wasm already contains {}", name.red())
+        let mut name = name.to_owned();
+        if let Some(&(prior, kind)) = self.exports.get(&name) {
+            match collision {
+                OnGlobalCollision::Error => return Err(ModuleModError::DuplicateExport(name)),
+                OnGlobalCollision::Reuse => {
+                    if kind != ExportKind::Global {
+                        return Err(ModuleModError::NotAGlobal(name));
+                    }
+                    let Some(existing) = self.globals.get(prior as usize) else {
+                        return Err(ModuleModError::MissingGlobal(name));
+                    };
+                    if existing.ty() != global.ty() {
+                        return Err(ModuleModError::GlobalTypeMismatch(name));
+                    }
+                    return Ok((name, GlobalIndex::from_u32(prior)));
+                }
+                OnGlobalCollision::Rename => {
+                    name = OnGlobalCollision::unique_name(&name, |x| self.exports.contains_key(x));
+                }
+            }
         }
-        let name = name.to_owned();
         let index = self.globals.len() as u32;
-        self.exports.insert(name, (index, ExportKind::Global));
+        self.exports
+            .insert(name.clone(), (index, ExportKind::Global));
         self.globals.push(global);
-        Ok(GlobalIndex::from_u32(index))
+        Ok((name, GlobalIndex::from_u32(index)))
     }
 
-    fn get_global(&mut self, name: &str) -> Result<GlobalIndex> {
+    fn get_global(&mut self, name: &str) -> Result<GlobalIndex, ModuleModError> {
         let Some((global, ExportKind::Global)) = self.exports.get(name) else {
-            bail!("missing global {}", name.red())
+            return Err(ModuleModError::MissingGlobal(name.to_owned()));
         };
         Ok(GlobalIndex::from_u32(*global))
     }
 
-    fn get_signature(&self, sig: SignatureIndex) -> Result<ArbFunctionType> {
-        let index = sig.as_u32() as usize;
-        let error = Report::msg(format!("missing signature {}", index.red()));
-        self.types.get(index).cloned().ok_or(error)
+    fn remove_global(&mut self, name: &str) -> Result<(), ModuleModError> {
+        let Some(&(global, ExportKind::Global)) = self.exports.get(name) else {
+            return Err(ModuleModError::MissingGlobal(name.to_owned()));
+        };
+        self.exports.remove(name);
+
+        let last = global as usize + 1 == self.globals.len();
+        let aliased = self
+            .exports
+            .values()
+            .any(|&export| export == (global, ExportKind::Global));
+        let used = self
+            .codes
+            .iter()
+            .flat_map(|code| &code.expr)
+            .any(|op| match op {
+                Operator::GlobalGet { global_index } | Operator::GlobalSet { global_index } => {
+                    *global_index == global
+                }
+                _ => false,
+            });
+        if last && !used && !aliased {
+            self.globals.pop();
+        }
+        Ok(())
+    }
+
+    fn rename_export(&mut self, old: &str, new: &str) -> Result<(), ModuleModError> {
+        if self.exports.contains_key(new) {
+            return Err(ModuleModError::DuplicateExport(new.to_owned()));
+        }
+        let Some(export) = self.exports.remove(old) else {
+            return Err(ModuleModError::MissingExport(old.to_owned()));
+        };
+        self.exports.insert(new.to_owned(), export);
+        Ok(())
+    }
+
+    fn get_signature(&self, sig: SignatureIndex) -> Result<ArbFunctionType, ModuleModError> {
+        let error = ModuleModError::MissingSignature(sig);
+        self.types.get(sig.as_u32() as usize).cloned().ok_or(error)
     }
 
-    fn get_function(&self, func: FunctionIndex) -> Result<ArbFunctionType> {
+    fn get_function(&self, func: FunctionIndex) -> Result<ArbFunctionType, ModuleModError> {
         let mut index = func.as_u32() as usize;
 
         let sig = if index < self.imports.len() {
@@ -293,13 +930,12 @@ impl<'a> ModuleMod for WasmBinary<'a> {
             self.functions.get(index)
         };
 
-        let func = func.as_u32();
         match sig {
             Some(sig) => self.get_signature(SignatureIndex::from_u32(*sig)),
-            None => match self.names.functions.get(&func) {
-                Some(name) => bail!("missing func {} @ index {}", name.red(), func.red()),
-                None => bail!("missing func @ index {}", func.red()),
-            },
+            None => {
+                let name = self.names.functions.get(&func.as_u32()).cloned();
+                Err(ModuleModError::MissingFunction(func, name))
+            }
         }
     }
 
@@ -336,9 +972,38 @@ impl<'a> ModuleMod for WasmBinary<'a> {
             .ok_or_else(|| eyre!("missing import {}", name.red()))
     }
 
-    fn move_start_function(&mut self, name: &str) -> Result<()> {
-        if let Some(prior) = self.exports.get(name) {
-            bail!("function {} already exists @ index {:?}", name.red(), prior)
+    fn func_imports(&self) -> Vec<(String, String, FunctionIndex)> {
+        let imports = self.imports.iter().enumerate();
+        let funcs = imports.map(|(index, import)| {
+            let func = FunctionIndex::from_u32(index as u32);
+            (import.module.to_owned(), import.name.to_owned(), func)
+        });
+        funcs.collect()
+    }
+
+    fn move_start_function_forced(
+        &mut self,
+        name: &str,
+        force: bool,
+    ) -> Result<Option<ExportKind>, ModuleModError> {
+        if let Some(start) = self.start {
+            let start = FunctionIndex::from_u32(start);
+            if (start.as_u32() as usize) < self.imports.len() {
+                return Err(ModuleModError::ImportedStart(start));
+            }
+            let ty = self.get_function(start)?;
+            if !ty.inputs.is_empty() || !ty.outputs.is_empty() {
+                return Err(ModuleModError::NonEmptyStart(start, ty));
+            }
+        }
+        let mut replaced = None;
+        if let Some(prior) = self.export_kind(name) {
+            if !force {
+                return Err(ModuleModError::DuplicateExport(name.to_owned()));
+            }
+            if self.start.is_some() {
+                replaced = Some(prior);
+            }
         }
 
         if let Some(start) = self.start.take() {
@@ -346,20 +1011,233 @@ impl<'a> ModuleMod for WasmBinary<'a> {
             self.exports.insert(name.clone(), (start, ExportKind::Func));
             self.names.functions.insert(start, name);
         }
+        Ok(replaced)
+    }
+
+    fn set_start_function(&mut self, func: FunctionIndex) -> Result<(), ModuleModError> {
+        if (func.as_u32() as usize) < self.imports.len() {
+            return Err(ModuleModError::ImportedStart(func));
+        }
+        let ty = self.get_function(func)?;
+        if !ty.inputs.is_empty() || !ty.outputs.is_empty() {
+            return Err(ModuleModError::NonEmptyStart(func, ty));
+        }
+        self.start = Some(func.as_u32());
         Ok(())
     }
 
-    fn memory_info(&self) -> Result<MemoryType> {
+    fn clear_start_function(&mut self) {
+        self.start = None;
+    }
+
+    fn start_function(&self) -> Option<FunctionIndex> {
+        self.start.map(FunctionIndex::from_u32)
+    }
+
+    fn start_function_type(&self) -> Result<Option<ArbFunctionType>> {
+        let Some(start) = self.start else {
+            return Ok(None);
+        };
+        Ok(Some(self.get_function(FunctionIndex::from_u32(start))?))
+    }
+
+    fn memory_info(&self) -> Result<MemoryType, ModuleModError> {
+        let missing = || ModuleModError::MissingMemory("memory".to_owned());
         if self.memories.is_empty() {
-            bail!("missing memory export with name {}", "memory".red());
+            return Err(missing());
         }
         if self.memories.len() > 1 {
-            bail!("only one memory is allowed");
+            return Err(ModuleModError::MultiMemoryUnsupported);
         }
         if self.exports.get("memory") != Some(&(0, ExportKind::Memory)) {
-            bail!("missing memory with export name {}", "memory".red());
+            return Err(missing());
+        }
+        let error = |err: Report| ModuleModError::UnsupportedMemory(err.to_string());
+        self.memories.last().unwrap().try_into().map_err(error)
+    }
+
+    fn ensure_memory_export(&mut self, name: &str) -> Result<()> {
+        if let Some(&(_, kind)) = self.exports.get(name) {
+            if kind != ExportKind::Memory {
+                bail!("export {} is not a memory", name.red())
+            }
+            return Ok(());
+        }
+        match self.memories.len() {
+            0 => bail!("cannot export {} without a memory", name.red()),
+            1 => {
+                let memory = (0, ExportKind::Memory);
+                self.exports.insert(name.to_owned(), memory);
+                Ok(())
+            }
+            count => bail!("cannot choose which of {} memories to export", count.red()),
+        }
+    }
+
+    fn memory_minimum(&self, index: u32) -> Result<Pages> {
+        let Some(memory) = self.memories.get(index as usize) else {
+            bail!("missing memory @ index {}", index.red())
+        };
+        Ok(Pages(memory.initial.try_into()?))
+    }
+
+    fn memory_maximum(&self, index: u32) -> Result<Option<Pages>> {
+        let Some(memory) = self.memories.get(index as usize) else {
+            bail!("missing memory @ index {}", index.red())
+        };
+        match memory.maximum {
+            Some(max) => Ok(Some(Pages(max.try_into()?))),
+            None => Ok(None),
+        }
+    }
+
+    fn memory_count(&self) -> u32 {
+        self.memories.len() as u32
+    }
+
+    fn limit_heap(&mut self, limit: Pages) -> Result<Vec<Pages>, ModuleModError> {
+        // find every bound before clamping any, so that failing leaves the module as it was
+        let error = |err: Report| ModuleModError::UnsupportedMemory(err.to_string());
+        let mut bounds = Vec::with_capacity(self.memories.len());
+        for (index, memory) in self.memories.iter().enumerate() {
+            let ty = MemoryType::try_from(memory).map_err(error)?;
+            if ty.min > limit {
+                return Err(ModuleModError::MemoryMinimumExceedsLimit {
+                    memory: index as u32,
+                    minimum: ty.min,
+                    limit,
+                });
+            }
+            bounds.push(ty.max.map_or(limit, |max| max.min(limit)));
+        }
+        for (memory, bound) in self.memories.iter_mut().zip(&bounds) {
+            memory.maximum = Some(bound.0.into());
+        }
+        Ok(bounds)
+    }
+
+    fn table_maximum(&self, index: u32) -> Result<Option<u32>> {
+        let Some(table) = self.tables.get(index as usize) else {
+            bail!("missing table @ index {}", index.red())
+        };
+        Ok(table.maximum)
+    }
+
+    fn table_count(&self) -> u32 {
+        self.tables.len() as u32
+    }
+
+    fn table_element_type(&self, index: u32) -> Result<ValType> {
+        let Some(table) = self.tables.get(index as usize) else {
+            bail!("missing table @ index {}", index.red())
+        };
+        Ok(table.element_type)
+    }
+
+    fn global_count(&self) -> u32 {
+        self.globals.len() as u32
+    }
+
+    fn global_type(&self, index: u32) -> Result<ArbValueType> {
+        let Some(global) = self.globals.get(index as usize) else {
+            bail!("missing global @ index {}", index.red())
+        };
+        Ok(global.ty())
+    }
+
+    fn func_import_count(&self) -> u32 {
+        self.imports.len() as u32
+    }
+
+    fn export_count(&self) -> u32 {
+        self.exports.len() as u32
+    }
+
+    fn export_kind(&self, name: &str) -> Option<ExportKind> {
+        self.exports.get(name).map(|x| x.1)
+    }
+
+    fn is_function_exported(&self, func: FunctionIndex) -> bool {
+        let func = (func.as_u32(), ExportKind::Func);
+        self.exports.values().any(|export| *export == func)
+    }
+
+    fn num_defined_functions(&self) -> usize {
+        self.functions.len()
+    }
+
+    fn passive_element_count(&self) -> usize {
+        let passive = self.elements.iter();
+        let passive = passive.filter(|elem| matches!(elem.kind, ElementKind::Passive));
+        passive.map(|elem| elem.items.len()).sum()
+    }
+
+    fn has_passive_data(&self) -> bool {
+        let passive = |data: &Data| matches!(data.kind, DataKind::Passive);
+        self.datas.iter().any(passive)
+    }
+
+    fn passive_data_count(&self) -> usize {
+        let passive = self.datas.iter();
+        let passive = passive.filter(|data| matches!(data.kind, DataKind::Passive));
+        passive.count()
+    }
+
+    fn passive_data_bytes(&self) -> usize {
+        let passive = self.datas.iter();
+        let passive = passive.filter(|data| matches!(data.kind, DataKind::Passive));
+        passive.map(|data| data.data.len()).sum()
+    }
+
+    fn uses_reference_types(&self) -> bool {
+        use ArbValueType::*;
+
+        let is_ref = |ty: &ArbValueType| matches!(ty, RefNull | FuncRef | InternalRef);
+        let sigs = self.types.iter();
+        let mut sigs = sigs.flat_map(|ty| ty.inputs.iter().chain(&ty.outputs));
+        let mut locals = self.codes.iter().flat_map(|code| &code.locals);
+        let mut ops = self.codes.iter().flat_map(|code| &code.expr);
+        let mut tables = self.tables.iter();
+
+        #[rustfmt::skip]
+        let is_ref_op = |op: &Operator| matches!(
+            op,
+            Operator::RefNull { .. } | Operator::RefIsNull | Operator::RefFunc { .. } |
+            Operator::TableGet { .. } | Operator::TableSet { .. } | Operator::TableGrow { .. } |
+            Operator::TableSize { .. } | Operator::TableFill { .. }
+        );
+        self.globals.iter().any(|global| is_ref(&global.ty()))
+            || tables.any(|table| table.element_type == ValType::ExternRef)
+            || sigs.any(is_ref)
+            || locals.any(|local| is_ref(&local.value))
+            || ops.any(is_ref_op)
+    }
+
+    fn abi_fingerprint(&self) -> [u8; 32] {
+        let func = |func| match self.get_function(FunctionIndex::from_u32(func)) {
+            Ok(ty) => format!("func {ty}"),
+            Err(_) => "func ?".to_owned(),
+        };
+
+        let mut entries = vec![];
+        for (index, import) in self.imports.iter().enumerate() {
+            let desc = func(index as u32);
+            entries.push(format!("import {} {}: {desc}", import.module, import.name));
+        }
+        for (name, &(index, kind)) in &self.exports {
+            let desc = match kind {
+                ExportKind::Func => func(index),
+                ExportKind::Global => match self.globals.get(index as usize) {
+                    Some(global) => format!("global {}", global.ty()),
+                    None => "global ?".to_owned(),
+                },
+                ExportKind::Memory => "memory".to_owned(),
+                ExportKind::Table => "table".to_owned(),
+                ExportKind::Tag => "tag".to_owned(),
+            };
+            entries.push(format!("export {name}: {desc}"));
         }
-        self.memories.last().unwrap().try_into()
+        hash_abi(entries)
     }
 }
 
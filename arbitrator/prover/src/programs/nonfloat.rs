@@ -0,0 +1,87 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use crate::value::ArbValueType;
+use arbutil::{operator::OperatorInfo, Color};
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Rejects floating-point arithmetic, comparisons, and conversions, along with any globals or
+/// signatures involving floats, for chains that don't permit softfloat.
+#[derive(Debug, Default)]
+pub struct NonFloat;
+
+impl<M: ModuleMod> Middleware<M> for NonFloat {
+    type FM<'a> = FuncNonFloat;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let is_float = |ty: &ArbValueType| matches!(ty, ArbValueType::F32 | ArbValueType::F64);
+
+        for global in 0..module.global_count() {
+            let ty = module.global_type(global)?;
+            if is_float(&ty) {
+                bail!("global {} has float type {}", global.red(), ty.red())
+            }
+        }
+        for (sig, ty) in module.all_signatures()? {
+            if ty.inputs.iter().chain(&ty.outputs).any(is_float) {
+                let sig = sig.as_u32().red();
+                bail!("signature {sig} has float type {}", ty.red())
+            }
+        }
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncNonFloat { func })
+    }
+
+    fn name(&self) -> &'static str {
+        "non-float"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncNonFloat {
+    /// The function being instrumented
+    func: LocalFunctionIndex,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncNonFloat {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        macro_rules! op {
+            ($first:ident $(,$opcode:ident)*) => {
+                $first $(| $opcode)*
+            };
+        }
+
+        #[rustfmt::skip]
+        let float = matches!(
+            op,
+            op!(F32Eq, F32Ne, F32Lt, F32Gt, F32Le, F32Ge, F64Eq, F64Ne, F64Lt, F64Gt, F64Le, F64Ge) |
+            op!(F32Abs, F32Neg, F32Ceil, F32Floor, F32Trunc, F32Nearest, F32Sqrt) |
+            op!(F32Add, F32Sub, F32Mul, F32Div, F32Min, F32Max, F32Copysign) |
+            op!(F64Abs, F64Neg, F64Ceil, F64Floor, F64Trunc, F64Nearest, F64Sqrt) |
+            op!(F64Add, F64Sub, F64Mul, F64Div, F64Min, F64Max, F64Copysign) |
+            op!(F32DemoteF64, F64PromoteF32)
+        );
+
+        if float || op.is_conversion() {
+            let func = self.func.as_u32().red();
+            bail!("func {func} uses float operator {}", op.code().red())
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "non-float"
+    }
+}
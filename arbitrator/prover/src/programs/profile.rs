@@ -0,0 +1,107 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use arbutil::operator::{OperatorCategory, OperatorInfo};
+use eyre::Result;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use wasmer_types::LocalFunctionIndex;
+use wasmparser::Operator;
+
+/// Static operator counts across a module, grouped by kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpcodeHistogram {
+    /// Blocks, branches, returns, and exception handling
+    pub control: u64,
+    /// Loads, stores, atomics, and bulk memory operations
+    pub memory: u64,
+    /// Constants, comparisons, arithmetic, and conversions
+    pub numeric: u64,
+    /// Direct and indirect calls
+    pub call: u64,
+    /// Drops and selects
+    pub parametric: u64,
+    /// Everything else, like variable, table, reference, and vector operations
+    pub other: u64,
+}
+
+impl OpcodeHistogram {
+    fn record(&mut self, op: &Operator) {
+        use Operator::*;
+
+        let count = match op {
+            Drop | Select | TypedSelect { .. } => &mut self.parametric,
+            op => match op.code().category() {
+                OperatorCategory::ControlFlow => &mut self.control,
+                OperatorCategory::Memory => &mut self.memory,
+                OperatorCategory::Arithmetic => &mut self.numeric,
+                OperatorCategory::Call => &mut self.call,
+                OperatorCategory::Simd | OperatorCategory::Other => &mut self.other,
+            },
+        };
+        *count += 1;
+    }
+
+    /// The number of operators counted.
+    pub fn total(&self) -> u64 {
+        self.control + self.memory + self.numeric + self.call + self.parametric + self.other
+    }
+}
+
+/// Tallies the module's operators by kind without changing them.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    /// The counts across every function instrumented so far
+    histogram: Arc<Mutex<OpcodeHistogram>>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The counts gathered by the last pass over a module.
+    pub fn summary(&self) -> OpcodeHistogram {
+        *self.histogram.lock()
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for Profiler {
+    type FM<'a> = FuncProfiler;
+
+    fn update_module(&self, _: &mut M) -> Result<()> {
+        *self.histogram.lock() = OpcodeHistogram::default();
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        let histogram = self.histogram.clone();
+        Ok(FuncProfiler { histogram })
+    }
+
+    fn name(&self) -> &'static str {
+        "profiler"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncProfiler {
+    /// The counts across every function instrumented so far
+    histogram: Arc<Mutex<OpcodeHistogram>>,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncProfiler {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        self.histogram.lock().record(&op);
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "profiler"
+    }
+}
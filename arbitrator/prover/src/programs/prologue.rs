@@ -0,0 +1,78 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use eyre::Result;
+use fnv::FnvHashSet as HashSet;
+use parking_lot::RwLock;
+use wasmer_types::{FunctionIndex, LocalFunctionIndex};
+use wasmparser::Operator;
+
+/// Injects a fixed sequence of operators at the start of each exported function, for guards
+/// that only externally-callable functions need. Internal helpers are left untouched.
+/// The prologue must leave the stack as it found it.
+#[derive(Debug)]
+pub struct ExportPrologue {
+    /// The operators to inject
+    prologue: Vec<Operator<'static>>,
+    /// The local functions the module exports
+    exported: RwLock<HashSet<LocalFunctionIndex>>,
+}
+
+impl ExportPrologue {
+    pub fn new(prologue: Vec<Operator<'static>>) -> Self {
+        Self {
+            prologue,
+            exported: RwLock::default(),
+        }
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for ExportPrologue {
+    type FM<'a> = FuncExportPrologue<'a>;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let imports = module.func_import_count();
+        let locals = module.num_defined_functions() as u32;
+
+        let exported = (0..locals).filter(|&local| {
+            let func = FunctionIndex::from_u32(imports + local);
+            module.is_function_exported(func)
+        });
+        *self.exported.write() = exported.map(LocalFunctionIndex::from_u32).collect();
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, func: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        let exported = self.exported.read().contains(&func);
+        let prologue = exported.then(|| self.prologue.clone());
+        Ok(FuncExportPrologue { prologue })
+    }
+
+    fn name(&self) -> &'static str {
+        "export prologue"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncExportPrologue<'a> {
+    /// The operators to inject before the first, if not yet injected
+    prologue: Option<Vec<Operator<'a>>>,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncExportPrologue<'a> {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        if let Some(prologue) = self.prologue.take() {
+            out.extend(prologue);
+        }
+        out.extend([op]);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "export prologue"
+    }
+}
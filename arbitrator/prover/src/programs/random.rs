@@ -0,0 +1,113 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use crate::value::{ArbValueType, FunctionType};
+use arbutil::Color;
+use eyre::{bail, Result};
+use parking_lot::RwLock;
+use wasmer_types::{FunctionIndex, GlobalIndex, GlobalInit, ImportIndex, LocalFunctionIndex, Type};
+use wasmparser::Operator;
+
+pub const STYLUS_RANDOM_COUNTER: &str = "stylus_random_counter";
+
+/// Replaces calls to `insecure_random` with calls to `seeded_random`, passing a counter that
+/// increments with each call so the host can derive each value deterministically from its seed.
+/// Modules that don't import `insecure_random` are left untouched.
+#[derive(Debug, Default)]
+pub struct DeterministicRandom {
+    /// The `insecure_random` and `seeded_random` imports
+    funcs: RwLock<Option<[FunctionIndex; 2]>>,
+    /// The number of random values requested so far
+    counter: RwLock<Option<GlobalIndex>>,
+}
+
+impl DeterministicRandom {
+    const INSECURE_FUNC: &'static str = "insecure_random";
+    const SEEDED_FUNC: &'static str = "seeded_random";
+}
+
+impl<M: ModuleMod> Middleware<M> for DeterministicRandom {
+    type FM<'a> = FuncDeterministicRandom;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let Ok(insecure) = module.get_import("vm_hooks", Self::INSECURE_FUNC) else {
+            return Ok(());
+        };
+        let seeded = module.get_import("vm_hooks", Self::SEEDED_FUNC)?;
+        let (ImportIndex::Function(insecure), ImportIndex::Function(seeded)) = (insecure, seeded)
+        else {
+            bail!("wrong import kind for {}", Self::INSECURE_FUNC.red());
+        };
+
+        let ty = module.get_function(insecure)?;
+        if ty != FunctionType::new(vec![], vec![ArbValueType::I64]) {
+            bail!("wrong type for {}: {}", Self::INSECURE_FUNC.red(), ty.red());
+        }
+        let ty = module.get_function(seeded)?;
+        if ty != FunctionType::new(vec![ArbValueType::I64], vec![ArbValueType::I64]) {
+            bail!("wrong type for {}: {}", Self::SEEDED_FUNC.red(), ty.red());
+        }
+
+        let init = GlobalInit::I64Const(0);
+        let counter = module.add_global(STYLUS_RANDOM_COUNTER, Type::I64, init)?;
+        *self.funcs.write() = Some([insecure, seeded]);
+        *self.counter.write() = Some(counter);
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        let funcs = *self.funcs.read();
+        let counter = *self.counter.read();
+        Ok(FuncDeterministicRandom {
+            rewrite: funcs.zip(counter),
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "deterministic random"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncDeterministicRandom {
+    /// The imports to rewrite between and the counter, if `insecure_random` is imported
+    rewrite: Option<([FunctionIndex; 2], GlobalIndex)>,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncDeterministicRandom {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        let Some(([insecure, seeded], counter)) = self.rewrite else {
+            out.extend([op]);
+            return Ok(());
+        };
+
+        match op {
+            Call { function_index } if function_index == insecure.as_u32() => {
+                let global_index = counter.as_u32();
+                out.extend([
+                    // pass the current count, then increment it
+                    GlobalGet { global_index },
+                    GlobalGet { global_index },
+                    I64Const { value: 1 },
+                    I64Add,
+                    GlobalSet { global_index },
+                    Call {
+                        function_index: seeded.as_u32(),
+                    },
+                ]);
+            }
+            _ => out.extend([op]),
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "deterministic random"
+    }
+}
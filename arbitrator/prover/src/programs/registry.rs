@@ -0,0 +1,50 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::ModuleMod;
+use arbutil::Color;
+use eyre::{bail, Result};
+use fnv::FnvHashMap as HashMap;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use wasmer_types::{GlobalIndex, GlobalInit, Type};
+
+/// Records which pass injected each global, so that passes applied together fail naming each
+/// other when they'd collide rather than with an anonymous duplicate export.
+/// Clones share the same records, so each pass in a stack should hold a clone of one registry.
+#[derive(Clone, Debug, Default)]
+pub struct GlobalRegistry {
+    /// Maps each injected global's name to the pass that added it
+    owners: Arc<Mutex<HashMap<String, &'static str>>>,
+}
+
+impl GlobalRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds and exports a global on behalf of the named pass, failing if another pass already
+    /// registered the name.
+    pub fn add_global<M: ModuleMod>(
+        &self,
+        module: &mut M,
+        pass: &'static str,
+        name: &str,
+        ty: Type,
+        init: GlobalInit,
+    ) -> Result<GlobalIndex> {
+        let mut owners = self.owners.lock();
+        if let Some(owner) = owners.get(name) {
+            let (pass, name, owner) = (pass.red(), name.red(), owner.red());
+            bail!("{pass} cannot add global {name}: already added by {owner}")
+        }
+        let global = module.add_global(name, ty, init)?;
+        owners.insert(name.to_owned(), pass);
+        Ok(global)
+    }
+
+    /// The pass that added the global with the given name, if any registered it.
+    pub fn owner(&self, name: &str) -> Option<&'static str> {
+        self.owners.lock().get(name).copied()
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use crate::binary::{ElementInit, ElementSegment, ExportKind, FuncImport, WasmBinary};
+use arbutil::Color;
+use eyre::{bail, Result};
+use fnv::FnvHashMap as HashMap;
+use std::{collections::BTreeSet, mem};
+use wasmer_types::FunctionIndex;
+use wasmparser::{BlockType, DataKind, ElementKind, Operator, ValType};
+
+/// Describes how a rewrite renumbers a module's index spaces.
+/// Indices absent from a map have been removed, while a missing map leaves that space untouched.
+#[derive(Clone, Debug, Default)]
+pub struct Remap {
+    pub funcs: Option<HashMap<u32, u32>>,
+    pub globals: Option<HashMap<u32, u32>>,
+    pub types: Option<HashMap<u32, u32>>,
+}
+
+impl Remap {
+    /// Assigns each retained index its position in the ordered set.
+    pub fn compact(retained: &BTreeSet<u32>) -> HashMap<u32, u32> {
+        retained
+            .iter()
+            .enumerate()
+            .map(|(new, &old)| (old, new as u32))
+            .collect()
+    }
+
+    pub fn func(&self, index: u32) -> Result<u32> {
+        Self::lookup(&self.funcs, index, "func")
+    }
+
+    pub fn global(&self, index: u32) -> Result<u32> {
+        Self::lookup(&self.globals, index, "global")
+    }
+
+    pub fn ty(&self, index: u32) -> Result<u32> {
+        Self::lookup(&self.types, index, "type")
+    }
+
+    fn lookup(map: &Option<HashMap<u32, u32>>, index: u32, kind: &str) -> Result<u32> {
+        let Some(map) = map else {
+            return Ok(index);
+        };
+        match map.get(&index) {
+            Some(index) => Ok(*index),
+            None => bail!("{kind} {} was removed but is still referenced", index.red()),
+        }
+    }
+
+    /// Renumbers the indices an operator refers to.
+    pub fn operator(&self, op: &mut Operator) -> Result<()> {
+        use Operator::*;
+        match op {
+            Call { function_index } | RefFunc { function_index } => {
+                *function_index = self.func(*function_index)?;
+            }
+            GlobalGet { global_index } | GlobalSet { global_index } => {
+                *global_index = self.global(*global_index)?;
+            }
+            CallIndirect { type_index, .. } => *type_index = self.ty(*type_index)?,
+            Block { blockty } | Loop { blockty } | If { blockty } => {
+                if let BlockType::FuncType(index) = blockty {
+                    *index = self.ty(*index)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Renumbers the function an element segment item refers to.
+    /// Constant expressions can't be rewritten, so the functions they reference must keep their indices.
+    pub fn element(&self, item: &mut ElementInit) -> Result<()> {
+        match item {
+            ElementInit::Func(func) => *func = self.func(*func)?,
+            ElementInit::Expr(expr) => {
+                if let Operator::RefFunc { function_index } = expr.get_operators_reader().read()? {
+                    if self.func(function_index)? != function_index {
+                        let func = function_index.red();
+                        bail!("cannot renumber func {func} used in an element expression")
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> WasmBinary<'a> {
+    /// Gets the type index of a function, accounting for the import offset.
+    pub(crate) fn func_type_index(&self, func: u32) -> Result<u32> {
+        let index = func as usize;
+        let ty = match index.checked_sub(self.imports.len()) {
+            None => self.imports.get(index).map(|x| x.offset),
+            Some(local) => self.functions.get(local).copied(),
+        };
+        match ty {
+            Some(ty) => Ok(ty),
+            None => bail!("missing func @ index {}", func.red()),
+        }
+    }
+
+    /// Rebinds an imported function to a different module and name.
+    /// The strings must outlive the binary, since imports borrow from the underlying wasm.
+    pub fn rename_import(
+        &mut self,
+        func: FunctionIndex,
+        module: &'a str,
+        name: &'a str,
+    ) -> Result<()> {
+        let Some(import) = self.imports.get_mut(func.as_u32() as usize) else {
+            bail!("func {} is not an import", func.as_u32().red())
+        };
+        import.module = module;
+        import.name = name;
+        Ok(())
+    }
+
+    /// Renumbers every index referenced by the module's code, element segments, exports, start
+    /// function, and names. Every lookup happens before anything changes, so on failure the module
+    /// is left as it was. Callers are responsible for removing the underlying items themselves.
+    pub fn remap(&mut self, remap: &Remap) -> Result<()> {
+        let mut codes = self.codes.clone();
+        for code in &mut codes {
+            for op in &mut code.expr {
+                remap.operator(op)?;
+            }
+        }
+        let mut elements = self.elements.clone();
+        for elem in &mut elements {
+            for item in &mut elem.items {
+                remap.element(item)?;
+            }
+        }
+        let mut exports = self.exports.clone();
+        for (index, kind) in exports.values_mut() {
+            *index = match kind {
+                ExportKind::Func => remap.func(*index)?,
+                ExportKind::Global => remap.global(*index)?,
+                _ => *index,
+            };
+        }
+        let start = self.start.map(|start| remap.func(start)).transpose()?;
+
+        self.codes = codes;
+        self.elements = elements;
+        self.exports = exports;
+        self.start = start;
+        for (func, name) in mem::take(&mut self.names.functions) {
+            if let Ok(func) = remap.func(func) {
+                self.names.functions.insert(func, name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Extracts an exported function and everything it transitively calls into a new, minimal module.
+    /// Memories and data segments are retained as-is, but tables are not, so indirect calls are unsupported.
+    /// Since globals are renumbered, data segments may not take their offsets from them.
+    pub fn extract_function(&self, name: &str) -> Result<WasmBinary<'a>> {
+        let Some(&(root, ExportKind::Func)) = self.exports.get(name) else {
+            bail!("missing func export {}", name.red())
+        };
+        let imports = self.imports.len() as u32;
+
+        for (index, data) in self.datas.iter().enumerate() {
+            let DataKind::Active { offset_expr, .. } = &data.kind else {
+                continue;
+            };
+            let mut reader = offset_expr.get_operators_reader();
+            while !reader.eof() {
+                if let Operator::GlobalGet { .. } = reader.read()? {
+                    let index = index.red();
+                    bail!(
+                        "cannot extract {} since data segment {index} is offset by a global",
+                        name.red()
+                    )
+                }
+            }
+        }
+
+        let mut closure = BTreeSet::new();
+        let mut refs = BTreeSet::new();
+        let mut globals = BTreeSet::new();
+        let mut types = BTreeSet::new();
+        let mut pending = vec![root];
+
+        while let Some(func) = pending.pop() {
+            if !closure.insert(func) {
+                continue;
+            }
+            types.insert(self.func_type_index(func)?);
+
+            let Some(local) = func.checked_sub(imports) else {
+                continue;
+            };
+            for op in &self.codes[local as usize].expr {
+                use Operator::*;
+                match op {
+                    Call { function_index } => pending.push(*function_index),
+                    RefFunc { function_index } => {
+                        refs.insert(*function_index);
+                        pending.push(*function_index)
+                    }
+                    GlobalGet { global_index } | GlobalSet { global_index } => {
+                        globals.insert(*global_index);
+                    }
+                    Block { blockty } | Loop { blockty } | If { blockty } => {
+                        if let BlockType::FuncType(ty) = blockty {
+                            types.insert(*ty);
+                        }
+                    }
+                    CallIndirect { .. } => {
+                        let func = func.red();
+                        bail!(
+                            "cannot extract {} since func {func} calls indirectly",
+                            name.red()
+                        )
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let remap = Remap {
+            funcs: Some(Remap::compact(&closure)),
+            globals: Some(Remap::compact(&globals)),
+            types: Some(Remap::compact(&types)),
+        };
+
+        let mut bin = WasmBinary {
+            types: types
+                .iter()
+                .map(|&x| self.types[x as usize].clone())
+                .collect(),
+            memories: self.memories.clone(),
+            globals: globals.iter().map(|&x| self.globals[x as usize]).collect(),
+            datas: self.datas.clone(),
+            ..WasmBinary::default()
+        };
+        bin.names.module = self.names.module.clone();
+
+        // the closure is ordered, so imports come before local functions
+        for &func in &closure {
+            let offset = remap.ty(self.func_type_index(func)?)?;
+            match func.checked_sub(imports) {
+                None => {
+                    let import = &self.imports[func as usize];
+                    bin.imports.push(FuncImport {
+                        offset,
+                        module: import.module,
+                        name: import.name,
+                    });
+                }
+                Some(local) => {
+                    let mut code = self.codes[local as usize].clone();
+                    for op in &mut code.expr {
+                        remap.operator(op)?;
+                    }
+                    bin.functions.push(offset);
+                    bin.codes.push(code);
+                }
+            }
+            if let Some(func_name) = self.names.functions.get(&func) {
+                bin.names
+                    .functions
+                    .insert(remap.func(func)?, func_name.clone());
+            }
+        }
+
+        // functions referenced via ref.func must be declared by an element segment
+        if !refs.is_empty() {
+            let declare = |&func: &u32| remap.func(func).map(ElementInit::Func);
+            bin.elements.push(ElementSegment {
+                kind: ElementKind::Declared,
+                ty: ValType::FuncRef,
+                items: refs.iter().map(declare).collect::<Result<_>>()?,
+                range: 0..0,
+            });
+        }
+
+        for (export, &(index, kind)) in &self.exports {
+            let index = match kind {
+                ExportKind::Func if index == root => remap.func(index)?,
+                ExportKind::Global if globals.contains(&index) => remap.global(index)?,
+                ExportKind::Memory => index,
+                _ => continue,
+            };
+            bin.exports.insert(export.clone(), (index, kind));
+        }
+        Ok(bin)
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{DefaultFuncMiddleware, Middleware, ModuleMod};
+use arbutil::Color;
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+
+/// Requires the module to declare exactly one memory, as the prover assumes.
+#[derive(Debug, Default)]
+pub struct ExactlyOneMemory;
+
+impl<M: ModuleMod> Middleware<M> for ExactlyOneMemory {
+    type FM<'a> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let count = module.memory_count();
+        if count != 1 {
+            bail!(
+                "module must have exactly one memory but has {}",
+                count.red()
+            );
+        }
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "exactly one memory"
+    }
+}
@@ -0,0 +1,31 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{DefaultFuncMiddleware, Middleware, ModuleMod};
+use arbutil::Color;
+use eyre::{bail, Result};
+use wasmer_types::LocalFunctionIndex;
+
+/// Requires the module to declare at most one table, as the prover assumes.
+#[derive(Debug, Default)]
+pub struct AtMostOneTable;
+
+impl<M: ModuleMod> Middleware<M> for AtMostOneTable {
+    type FM<'a> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let count = module.table_count();
+        if count > 1 {
+            bail!("module may have at most one table but has {}", count.red());
+        }
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "at most one table"
+    }
+}
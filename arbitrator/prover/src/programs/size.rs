@@ -0,0 +1,74 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use eyre::Result;
+use parking_lot::RwLock;
+use wasmer_types::{GlobalIndex, GlobalInit, LocalFunctionIndex, Type};
+use wasmparser::Operator;
+
+pub const STYLUS_MEMORY_SIZE: &str = "stylus_memory_size";
+
+/// Mirrors `memory.size` in a global, which is updated after each `memory.grow`.
+/// This lets the size be read cheaply, such as by the host.
+#[derive(Debug, Default)]
+pub struct MemorySizeGlobal {
+    /// The global mirroring the size of memory, in pages
+    global: RwLock<Option<GlobalIndex>>,
+}
+
+impl<M: ModuleMod> Middleware<M> for MemorySizeGlobal {
+    type FM<'a> = FuncMemorySizeGlobal;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let pages = module.memory_minimum(0)?.0 as i32;
+        let init = GlobalInit::I32Const(pages);
+        let global = module.add_global(STYLUS_MEMORY_SIZE, Type::I32, init)?;
+        *self.global.write() = Some(global);
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        let global = self.global.read().expect("no global");
+        Ok(FuncMemorySizeGlobal { global })
+    }
+
+    fn name(&self) -> &'static str {
+        "memory size global"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncMemorySizeGlobal {
+    /// The global mirroring the size of memory, in pages
+    global: GlobalIndex,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncMemorySizeGlobal {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        let grow = matches!(op, MemoryGrow { .. });
+        out.extend([op]);
+
+        // grow can fail, so re-read the size rather than adding the delta
+        if grow {
+            let global_index = self.global.as_u32();
+            out.extend([
+                MemorySize {
+                    mem: 0,
+                    mem_byte: 0,
+                },
+                GlobalSet { global_index },
+            ]);
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "memory size global"
+    }
+}
@@ -1,9 +1,16 @@
 // Copyright 2022-2023, Offchain Labs, Inc.
 // For license information, see https://github.com/nitro/blob/master/LICENSE
 
-use super::{DefaultFuncMiddleware, Middleware, ModuleMod};
-use eyre::Result;
-use wasmer_types::LocalFunctionIndex;
+use super::{DefaultFuncMiddleware, Middleware, ModuleMod, ModuleModError};
+use crate::{
+    binary::{Code, ExportKind, WasmBinary},
+    value::FunctionType,
+};
+use arbutil::Color;
+use derivative::Derivative;
+use eyre::{bail, Result};
+use wasmer_types::{GlobalIndex, GlobalInit, LocalFunctionIndex, Type};
+use wasmparser::Operator;
 
 #[cfg(feature = "native")]
 use wasmer::TypedFunction;
@@ -17,7 +24,7 @@ impl<M: ModuleMod> Middleware<M> for StartMover {
     type FM<'a> = DefaultFuncMiddleware;
 
     fn update_module(&self, module: &mut M) -> Result<()> {
-        module.move_start_function(STYLUS_START)
+        Ok(module.move_start_function(STYLUS_START)?)
     }
 
     fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
@@ -29,6 +36,116 @@ impl<M: ModuleMod> Middleware<M> for StartMover {
     }
 }
 
+/// Generates a hook's body given the indices of the globals it registered.
+pub type HookBody = Box<dyn Fn(&[GlobalIndex]) -> Vec<Operator<'static>> + Send + Sync>;
+
+/// Composes setup code that must run before a module's original start function.
+/// Each hook becomes a function of its own, and a synthesized start calls them in order before
+/// calling the original, if any. Since this adds functions, only [`WasmBinary`] is supported.
+#[derive(Debug, Default)]
+pub struct StartHooks {
+    hooks: Vec<StartHook>,
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct StartHook {
+    /// Identifies the hook in errors and the name section
+    name: String,
+    /// Globals to register before generating the body
+    globals: Vec<(String, Type, GlobalInit)>,
+    #[derivative(Debug = "ignore")]
+    body: HookBody,
+}
+
+impl StartHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a hook that runs after those already added.
+    /// The body's operators mustn't include the final `end`, which is added automatically.
+    pub fn hook(
+        mut self,
+        name: &str,
+        globals: Vec<(String, Type, GlobalInit)>,
+        body: HookBody,
+    ) -> Self {
+        let name = name.to_owned();
+        self.hooks.push(StartHook {
+            name,
+            globals,
+            body,
+        });
+        self
+    }
+
+    /// Synthesizes the combined start function and exports it under the given name,
+    /// clearing the module's start as [`ModuleMod::move_start_function`] would.
+    pub fn apply(&self, bin: &mut WasmBinary, export: &str) -> Result<()> {
+        if bin.exports.contains_key(export) {
+            bail!(ModuleModError::DuplicateExport(export.to_owned()))
+        }
+
+        let unit = FunctionType::default();
+        let offset = match bin.types.iter().position(|ty| *ty == unit) {
+            Some(offset) => offset as u32,
+            None => {
+                bin.types.push(unit);
+                bin.types.len() as u32 - 1
+            }
+        };
+
+        let mut calls = vec![];
+        for hook in &self.hooks {
+            let mut globals = vec![];
+            for (global, ty, init) in &hook.globals {
+                let index = match bin.add_global(global, *ty, *init) {
+                    Ok(index) => index,
+                    Err(ModuleModError::DuplicateExport(_)) => {
+                        let (hook, global) = (hook.name.red(), global.red());
+                        bail!("start hook {hook} cannot re-register existing export {global}")
+                    }
+                    Err(error) => bail!(error),
+                };
+                globals.push(index);
+            }
+            let func = Self::add_func(bin, offset, &hook.name, (hook.body)(&globals));
+            calls.push(Operator::Call {
+                function_index: func,
+            });
+        }
+        if let Some(start) = bin.start.take() {
+            calls.push(Operator::Call {
+                function_index: start,
+            });
+        }
+
+        let start = Self::add_func(bin, offset, export, calls);
+        bin.exports
+            .insert(export.to_owned(), (start, ExportKind::Func));
+        Ok(())
+    }
+
+    /// Appends a named local function of the given type, returning its index.
+    fn add_func<'a>(
+        bin: &mut WasmBinary<'a>,
+        ty: u32,
+        name: &str,
+        mut expr: Vec<Operator<'a>>,
+    ) -> u32 {
+        let func = (bin.imports.len() + bin.codes.len()) as u32;
+        expr.push(Operator::End);
+        bin.functions.push(ty);
+        bin.codes.push(Code {
+            locals: vec![],
+            expr,
+        });
+        bin.names.functions.insert(func, name.to_owned());
+        func
+    }
+}
+
 #[cfg(feature = "native")]
 pub trait StartlessMachine {
     fn get_start(&self) -> Result<TypedFunction<(), ()>>;
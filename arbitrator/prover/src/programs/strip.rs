@@ -0,0 +1,94 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{rewrite::Remap, DefaultFuncMiddleware, Middleware};
+use crate::binary::WasmBinary;
+use eyre::Result;
+use std::{collections::BTreeSet, mem};
+use wasmer_types::{FunctionIndex, LocalFunctionIndex};
+
+/// Removes imported functions the module never uses, renumbering the functions that remain.
+#[derive(Debug, Default)]
+pub struct StripUnusedImports;
+
+impl<'a> Middleware<WasmBinary<'a>> for StripUnusedImports {
+    type FM<'b> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut WasmBinary<'a>) -> Result<()> {
+        let used = module.used_imports()?;
+        let imports = module.imports.len() as u32;
+        let funcs = imports + module.codes.len() as u32;
+
+        let is_used = |func: u32| func >= imports || used.contains(&FunctionIndex::from_u32(func));
+        let retained: BTreeSet<u32> = (0..funcs).filter(|&func| is_used(func)).collect();
+        if retained.len() == funcs as usize {
+            return Ok(());
+        }
+
+        let remap = Remap {
+            funcs: Some(Remap::compact(&retained)),
+            ..Remap::default()
+        };
+        module.remap(&remap)?;
+
+        let imports = mem::take(&mut module.imports).into_iter().enumerate();
+        let imports = imports.filter(|(index, _)| is_used(*index as u32));
+        module.imports = imports.map(|(_, import)| import).collect();
+        Ok(())
+    }
+
+    fn instrument<'b>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'b>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "strip unused imports"
+    }
+}
+
+/// Removes local functions nothing refers to, renumbering the functions that remain.
+/// Since removal may leave other functions unreferenced, this repeats until none are dead.
+/// Tabled functions keep their indices, so only functions after the last of them are removed.
+#[derive(Debug, Default)]
+pub struct StripDeadFunctions;
+
+impl<'a> Middleware<WasmBinary<'a>> for StripDeadFunctions {
+    type FM<'b> = DefaultFuncMiddleware;
+
+    fn update_module(&self, module: &mut WasmBinary<'a>) -> Result<()> {
+        let imports = module.imports.len() as u32;
+        let tabled = module.element_funcs()?;
+        let floor = tabled.into_iter().max().map_or(0, |x| x + 1);
+
+        loop {
+            let funcs = imports + module.codes.len() as u32;
+            let dead = module.dead_functions()?.into_iter();
+            let dead = dead.map(|x| x.as_u32() + imports).filter(|&x| x >= floor);
+            let dead: BTreeSet<u32> = dead.collect();
+            if dead.is_empty() {
+                return Ok(());
+            }
+
+            let retained: BTreeSet<u32> = (0..funcs).filter(|func| !dead.contains(func)).collect();
+            let remap = Remap {
+                funcs: Some(Remap::compact(&retained)),
+                ..Remap::default()
+            };
+            module.remap(&remap)?;
+
+            let is_live = |local: usize| !dead.contains(&(local as u32 + imports));
+            let codes = mem::take(&mut module.codes).into_iter().enumerate();
+            module.codes = codes.filter(|(x, _)| is_live(*x)).map(|x| x.1).collect();
+            let types = mem::take(&mut module.functions).into_iter().enumerate();
+            module.functions = types.filter(|(x, _)| is_live(*x)).map(|x| x.1).collect();
+        }
+    }
+
+    fn instrument<'b>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'b>> {
+        Ok(DefaultFuncMiddleware)
+    }
+
+    fn name(&self) -> &'static str {
+        "strip dead functions"
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::config::CompileConfig;
+use crate::binary::WasmBinary;
+use arbutil::Color;
+use eyre::{bail, Result};
+use serde::{Deserialize, Serialize};
+use wasmparser::{Parser, Payload};
+
+pub const SUMMARY_SECTION: &str = "stylus.summary";
+
+/// How a custom section's contents are encoded.
+/// The first byte of the section names the format so that readers can dispatch on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ConfigFormat {
+    Json,
+    Bincode,
+}
+
+impl TryFrom<u8> for ConfigFormat {
+    type Error = eyre::Error;
+
+    fn try_from(tag: u8) -> Result<Self> {
+        Ok(match tag {
+            0 => Self::Json,
+            1 => Self::Bincode,
+            _ => bail!("unknown section format {}", tag.red()),
+        })
+    }
+}
+
+/// A digest of a module, stored in a custom section for introspection without a full parse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleSummary {
+    /// The number of functions, including imports
+    pub funcs: u32,
+    /// The maximum size of memory in pages, if bounded
+    pub memory_max: Option<u32>,
+    /// The version of the pricing table the module is compiled against
+    pub pricing_version: u16,
+}
+
+impl ModuleSummary {
+    /// Encodes the summary, prefixed by a tag naming the format.
+    pub fn to_bytes(self, format: ConfigFormat) -> Result<Vec<u8>> {
+        let mut data = vec![format as u8];
+        match format {
+            ConfigFormat::Json => serde_json::to_writer(&mut data, &self)?,
+            ConfigFormat::Bincode => bincode::serialize_into(&mut data, &self)?,
+        }
+        Ok(data)
+    }
+
+    /// Decodes a summary in whichever format its tag names.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let Some((&tag, data)) = data.split_first() else {
+            bail!("empty summary section")
+        };
+        Ok(match ConfigFormat::try_from(tag)? {
+            ConfigFormat::Json => serde_json::from_slice(data)?,
+            ConfigFormat::Bincode => bincode::deserialize(data)?,
+        })
+    }
+
+    /// Appends the summary to a wasm as a custom section.
+    pub fn append_to(self, wasm: &[u8], format: ConfigFormat) -> Result<Vec<u8>> {
+        fn leb128(mut value: usize, out: &mut Vec<u8>) {
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                match value {
+                    0 => return out.push(byte),
+                    _ => out.push(byte | 0x80),
+                }
+            }
+        }
+
+        let mut payload = vec![];
+        leb128(SUMMARY_SECTION.len(), &mut payload);
+        payload.extend(SUMMARY_SECTION.as_bytes());
+        payload.extend(self.to_bytes(format)?);
+
+        let mut wasm = wasm.to_vec();
+        wasm.push(0); // custom section id
+        leb128(payload.len(), &mut wasm);
+        wasm.extend(payload);
+        Ok(wasm)
+    }
+
+    /// Reads a summary from a wasm's custom sections, if present.
+    pub fn read_from(wasm: &[u8]) -> Result<Option<Self>> {
+        for payload in Parser::new(0).parse_all(wasm) {
+            if let Payload::CustomSection(reader) = payload? {
+                if reader.name() == SUMMARY_SECTION {
+                    return Self::from_bytes(reader.data()).map(Some);
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> WasmBinary<'a> {
+    /// Summarizes the module for its [`SUMMARY_SECTION`].
+    pub fn summary(&self, compile: &CompileConfig) -> Result<ModuleSummary> {
+        let memory_max = match self.memories.first().and_then(|x| x.maximum) {
+            Some(max) => Some(max.try_into()?),
+            None => None,
+        };
+        Ok(ModuleSummary {
+            funcs: (self.imports.len() + self.codes.len()).try_into()?,
+            memory_max,
+            pricing_version: compile.version,
+        })
+    }
+}
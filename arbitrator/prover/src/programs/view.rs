@@ -0,0 +1,74 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{ModuleMod, ModuleModError};
+use crate::{
+    binary::{ExportKind, ExportMap, WasmBinary},
+    memory::MemoryType,
+    value::FunctionType as ArbFunctionType,
+};
+use eyre::Result;
+use fnv::{FnvHashMap as HashMap, FnvHashSet as HashSet};
+use wasmer_types::{FunctionIndex, GlobalIndex, ImportIndex, Pages, SignatureIndex};
+
+/// An immutable view of a [`WasmBinary`] exposing only the read-only parts of [`ModuleMod`].
+/// Since nothing can be mutated through it, a view may be freely shared across analysis threads.
+#[derive(Clone, Copy)]
+pub struct WasmBinaryView<'a> {
+    binary: &'a WasmBinary<'a>,
+}
+
+impl<'a> WasmBinary<'a> {
+    pub fn view(&'a self) -> WasmBinaryView<'a> {
+        WasmBinaryView { binary: self }
+    }
+}
+
+impl<'a> WasmBinaryView<'a> {
+    pub fn exports(&self) -> &ExportMap {
+        &self.binary.exports
+    }
+
+    pub fn get_global(&self, name: &str) -> Result<GlobalIndex, ModuleModError> {
+        let Some(&(global, ExportKind::Global)) = self.binary.exports.get(name) else {
+            return Err(ModuleModError::MissingGlobal(name.to_owned()));
+        };
+        Ok(GlobalIndex::from_u32(global))
+    }
+
+    pub fn get_signature(&self, sig: SignatureIndex) -> Result<ArbFunctionType, ModuleModError> {
+        self.binary.get_signature(sig)
+    }
+
+    pub fn get_function(&self, func: FunctionIndex) -> Result<ArbFunctionType, ModuleModError> {
+        self.binary.get_function(func)
+    }
+
+    pub fn all_functions(&self) -> Result<HashMap<FunctionIndex, ArbFunctionType>> {
+        self.binary.all_functions()
+    }
+
+    pub fn all_signatures(&self) -> Result<HashMap<SignatureIndex, ArbFunctionType>> {
+        self.binary.all_signatures()
+    }
+
+    pub fn get_import(&self, module: &str, name: &str) -> Result<ImportIndex> {
+        self.binary.get_import(module, name)
+    }
+
+    pub fn memory_info(&self) -> Result<MemoryType, ModuleModError> {
+        self.binary.memory_info()
+    }
+
+    pub fn memory_minimum(&self, index: u32) -> Result<Pages> {
+        self.binary.memory_minimum(index)
+    }
+
+    pub fn element_funcs(&self) -> Result<Vec<u32>> {
+        self.binary.element_funcs()
+    }
+
+    pub fn used_imports(&self) -> Result<HashSet<FunctionIndex>> {
+        self.binary.used_imports()
+    }
+}
@@ -0,0 +1,107 @@
+// Copyright 2024, Offchain Labs, Inc.
+// For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+use super::{FuncMiddleware, Middleware, ModuleMod};
+use arbutil::operator::OperatorInfo;
+use eyre::Result;
+use parking_lot::RwLock;
+use wasmer_types::{GlobalIndex, GlobalInit, LocalFunctionIndex, Type};
+use wasmparser::{BlockType, Operator};
+
+pub const STYLUS_STEPS: &str = "stylus_steps";
+
+/// Limits the total number of operators a program may execute, independent of ink.
+/// Each basic block adds its length to a global counter on entry, trapping once past the limit.
+#[derive(Debug)]
+pub struct StepWatchdog {
+    /// The most operators the program may execute
+    limit: u64,
+    /// The number of operators executed so far
+    global: RwLock<Option<GlobalIndex>>,
+}
+
+impl StepWatchdog {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            global: RwLock::default(),
+        }
+    }
+
+    pub fn global(&self) -> GlobalIndex {
+        self.global.read().expect("missing global")
+    }
+}
+
+impl<M: ModuleMod> Middleware<M> for StepWatchdog {
+    type FM<'a> = FuncStepWatchdog<'a>;
+
+    fn update_module(&self, module: &mut M) -> Result<()> {
+        let steps = module.add_global(STYLUS_STEPS, Type::I64, GlobalInit::I64Const(0))?;
+        *self.global.write() = Some(steps);
+        Ok(())
+    }
+
+    fn instrument<'a>(&self, _: LocalFunctionIndex) -> Result<Self::FM<'a>> {
+        Ok(FuncStepWatchdog {
+            global: self.global(),
+            limit: self.limit,
+            block: vec![],
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "step watchdog"
+    }
+}
+
+#[derive(Debug)]
+pub struct FuncStepWatchdog<'a> {
+    /// The number of operators executed so far
+    global: GlobalIndex,
+    /// The most operators the program may execute
+    limit: u64,
+    /// Instructions of the current basic block
+    block: Vec<Operator<'a>>,
+}
+
+impl<'a> FuncMiddleware<'a> for FuncStepWatchdog<'a> {
+    fn feed<O>(&mut self, op: Operator<'a>, out: &mut O) -> Result<()>
+    where
+        O: Extend<Operator<'a>>,
+    {
+        use Operator::*;
+
+        let end = op.ends_basic_block();
+        self.block.push(op);
+
+        if end {
+            let global_index = self.global.as_u32();
+            let steps = self.block.len() as i64;
+            let blockty = BlockType::Empty;
+
+            out.extend([
+                // steps += block length
+                GlobalGet { global_index },
+                I64Const { value: steps },
+                I64Add,
+                GlobalSet { global_index },
+                // if steps > limit => panic
+                GlobalGet { global_index },
+                I64Const {
+                    value: self.limit as i64,
+                },
+                I64GtU,
+                If { blockty },
+                Unreachable,
+                End,
+            ]);
+            out.extend(self.block.drain(..));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "step watchdog"
+    }
+}
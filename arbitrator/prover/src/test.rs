@@ -3,10 +3,62 @@
 
 #![cfg(test)]
 
-use crate::binary;
+use crate::{
+    binary::{self, ExportKind, Local},
+    machine::{get_empty_preimage_resolver, GlobalState},
+    programs::{
+        align::AlignmentCheck,
+        analysis::ESTIMATED_BYTES_PER_OP,
+        balance::ControlBalanceCheck,
+        config::{
+            AllowedProposals, CompileConfig, CompileMemoryParams, CompilePricingParams,
+            OpcodePricing, PageRounding,
+        },
+        constant::ConstBound,
+        data::DataSegmentCheck,
+        drop::DropElimination,
+        dynamic::{DynamicMeter, SCRATCH_GLOBAL},
+        elements::PassiveElementBound,
+        exception::ExceptionCheck,
+        exports::CanonicalizeExports,
+        features::FeatureCheck,
+        global::GlobalCheck,
+        heap::HeapBound,
+        imports::ImportAllowlist,
+        limits::ModuleLimits,
+        locals::LocalsBound,
+        meter::{Meter, MeterGranularity, STYLUS_INK_LEFT, STYLUS_INK_STATUS},
+        nonfloat::NonFloat,
+        profile::{OpcodeHistogram, Profiler},
+        prologue::ExportPrologue,
+        random::{DeterministicRandom, STYLUS_RANDOM_COUNTER},
+        registry::GlobalRegistry,
+        rewrite::Remap,
+        single_memory::ExactlyOneMemory,
+        single_table::AtMostOneTable,
+        size::{MemorySizeGlobal, STYLUS_MEMORY_SIZE},
+        start::{StartHooks, StartMover, STYLUS_START},
+        strip::{StripDeadFunctions, StripUnusedImports},
+        summary::{ConfigFormat, ModuleSummary},
+        watchdog::{StepWatchdog, STYLUS_STEPS},
+        FuncMiddleware, Middleware, ModuleMod, ModuleModError, OnGlobalCollision,
+    },
+    value::{ArbValueType, FunctionType, Value},
+    Machine,
+};
+use arbutil::{
+    operator::{OperatorCategory, OperatorInfo},
+    Color,
+};
 use brotli::Dictionary;
 use eyre::Result;
+use fnv::FnvHashMap as HashMap;
 use std::path::Path;
+use wasmer_types::{
+    FunctionIndex, GlobalIndex, GlobalInit, LocalFunctionIndex, Pages, Type, WASM_MAX_PAGES,
+    WASM_PAGE_SIZE,
+};
+use wasmparser::{ElementKind, Operator, ValType};
 
 fn as_wasm(wat: &str) -> Vec<u8> {
     let wasm = wasmer::wat2wasm(wat.as_bytes());
@@ -55,6 +107,2504 @@ pub fn reject_ambiguous_imports() {
     let _ = binary::parse(&wasm, Path::new("")).unwrap_err();
 }
 
+fn binary_machine(bin: binary::WasmBinary) -> Result<Machine> {
+    Machine::from_binaries(
+        &[],
+        bin,
+        false,
+        false,
+        false,
+        false,
+        false,
+        GlobalState::default(),
+        HashMap::default(),
+        get_empty_preimage_resolver(),
+        None,
+    )
+}
+
+/// Meters a function consisting of a lone operator, returning the ink charged.
+fn meter_charge(pricing: &CompilePricingParams, op: Operator<'static>) -> Result<i64> {
+    let wasm = as_wasm("(module (func))");
+    let meter = Meter::new(pricing);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    meter.update_module(&mut bin)?;
+
+    let func = LocalFunctionIndex::from_u32(0);
+    let mut mid = Middleware::<binary::WasmBinary>::instrument(&meter, func)?;
+    let mut out = vec![];
+    mid.feed(op, &mut out)?;
+    mid.feed(Operator::End, &mut out)?;
+
+    let costs = out.iter().filter_map(|op| match op {
+        Operator::I64Const { value } => Some(*value),
+        _ => None,
+    });
+    Ok(costs.max().unwrap_or_default())
+}
+
+fn run_binary(bin: binary::WasmBinary, func: &str, args: Vec<Value>) -> Result<Vec<Value>> {
+    binary_machine(bin)?.call_function("user", func, args)
+}
+
+#[test]
+pub fn test_extract_function() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "env" "unrelated_import" (func (param i32)))
+            (func $helper (param i32) (result i32)
+                local.get 0
+                i32.const 1
+                i32.add)
+            (func $unrelated (result i32)
+                i32.const 7)
+            (func (export "entry") (param i32) (result i32)
+                local.get 0
+                call $helper
+                call $helper)
+            (func (export "other") (result i32)
+                call $unrelated)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let mini = bin.extract_function("entry")?;
+    assert_eq!(mini.imports.len(), 0);
+    assert_eq!(mini.codes.len(), 2);
+    assert_eq!(mini.exports.len(), 1);
+    assert!(bin.extract_function("missing").is_err());
+
+    let output = run_binary(mini, "entry", vec![Value::I32(40)])?;
+    assert_eq!(output, vec![Value::I32(42)]);
+
+    // funcs referenced via ref.func are declared, here $unrelated at its new index
+    let refer = [Operator::RefFunc { function_index: 2 }, Operator::Drop];
+    bin.codes[2].expr.splice(0..0, refer);
+    let mini = bin.extract_function("entry")?;
+    assert_eq!(mini.codes.len(), 3);
+    assert_eq!(mini.element_funcs()?, vec![1]);
+    assert!(matches!(mini.elements[0].kind, ElementKind::Declared));
+    Ok(())
+}
+
+#[test]
+pub fn test_global_collisions() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (global (export "taken") (mut i32) (i32.const 0))
+            (global (export "taken_1") (mut i32) (i32.const 0))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let init = GlobalInit::I32Const(1);
+
+    let error = OnGlobalCollision::Error;
+    assert!(bin.add_global_as("taken", Type::I32, init, error).is_err());
+    assert!(bin.add_global("taken", Type::I32, init).is_err());
+
+    let reuse = OnGlobalCollision::Reuse;
+    let (name, index) = bin.add_global_as("taken", Type::I32, init, reuse)?;
+    assert_eq!((name.as_str(), index), ("taken", GlobalIndex::from_u32(0)));
+    let wrong_type = GlobalInit::I64Const(1);
+    assert!(bin
+        .add_global_as("taken", Type::I64, wrong_type, reuse)
+        .is_err());
+
+    let rename = OnGlobalCollision::Rename;
+    let (name, index) = bin.add_global_as("taken", Type::I32, init, rename)?;
+    assert_eq!(
+        (name.as_str(), index),
+        ("taken_2", GlobalIndex::from_u32(2))
+    );
+    assert_eq!(bin.get_global("taken_2")?, index);
+    Ok(())
+}
+
+#[test]
+pub fn test_memory_minimum() -> Result<()> {
+    let wasm = as_wasm(r#"(module (memory (export "memory") 3 10))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.memory_minimum(0)?, Pages(3));
+    assert!(bin.memory_minimum(1).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_data_segment_modes() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (data (i32.const 0) "active")
+            (data $passive "passive")
+            (func
+                i32.const 0
+                i32.const 0
+                i32.const 4
+                memory.init $passive
+                data.drop $passive)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&DataSegmentCheck::default())?;
+
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (data (i32.const 0) "active")
+            (func
+                i32.const 0
+                i32.const 0
+                i32.const 4
+                memory.init 0)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let err = bin
+        .apply_middleware(&DataSegmentCheck::default())
+        .unwrap_err();
+    assert!(format!("{err:?}").contains("active data segment"));
+    Ok(())
+}
+
+#[test]
+pub fn test_used_imports() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "called" (func $called))
+            (import "vm_hooks" "uncalled" (func $uncalled))
+            (func
+                call $called)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let used = bin.used_imports()?;
+    assert_eq!(used.len(), 1);
+    assert!(used.contains(&FunctionIndex::from_u32(0)));
+    Ok(())
+}
+
+#[test]
+pub fn test_strip_unused_imports() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "unused" (func $unused))
+            (import "vm_hooks" "used" (func $used))
+            (func $helper (result i32)
+                i32.const 5)
+            (func (export "entry") (result i32)
+                call $used
+                call $helper)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&StripUnusedImports)?;
+
+    assert_eq!(bin.imports.len(), 1);
+    assert_eq!(bin.imports[0].name, "used");
+    assert_eq!(bin.exports["entry"], (2, ExportKind::Func));
+    assert_eq!(bin.names.functions[&2], "entry");
+
+    let calls: Vec<_> = bin.codes[1]
+        .expr
+        .iter()
+        .filter_map(|op| match op {
+            Operator::Call { function_index } => Some(*function_index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(calls, vec![0, 1]);
+    Ok(())
+}
+
+#[test]
+pub fn test_strip_unused_imports_tabled() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "unused" (func $unused))
+            (type $ret (func (result i32)))
+            (table 1 funcref)
+            (elem (i32.const 0) $helper)
+            (func $helper (result i32)
+                i32.const 5)
+            (func (export "entry") (result i32)
+                i32.const 0
+                call_indirect (type $ret))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&StripUnusedImports)?;
+    assert_eq!(bin.imports.len(), 0);
+    assert_eq!(bin.element_funcs()?, vec![0]);
+
+    // the element segment is renumbered, but the entry's export is missing, so nothing changes
+    let remap = Remap {
+        funcs: Some([(0, 1)].into_iter().collect()),
+        ..Remap::default()
+    };
+    assert!(bin.remap(&remap).is_err());
+    assert_eq!(bin.element_funcs()?, vec![0]);
+    assert_eq!(bin.exports["entry"], (1, ExportKind::Func));
+
+    let output = run_binary(bin, "entry", vec![])?;
+    assert_eq!(output, vec![Value::I32(5)]);
+    Ok(())
+}
+
+#[test]
+pub fn test_binary_view() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "used" (func $used (param i32)))
+            (memory 3)
+            (global $counter (export "counter") (mut i32) (i32.const 0))
+            (func (export "entry") (param i32) (result i32)
+                local.get 0
+                call $used
+                global.get $counter)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let view = bin.view();
+
+    // analysis only needs to read, so many threads may share the same view
+    std::thread::scope(|scope| {
+        scope.spawn(|| assert_eq!(view.exports()["entry"], (1, ExportKind::Func)));
+        scope.spawn(|| {
+            assert_eq!(
+                view.get_global("counter").unwrap(),
+                GlobalIndex::from_u32(0)
+            )
+        });
+        scope.spawn(|| assert_eq!(view.memory_minimum(0).unwrap(), Pages(3)));
+    });
+
+    let entry = view.get_function(FunctionIndex::from_u32(1))?;
+    assert_eq!(entry.inputs.len(), 1);
+    assert_eq!(entry.outputs.len(), 1);
+    assert_eq!(view.all_functions()?.len(), 2);
+    assert_eq!(view.used_imports()?.len(), 1);
+    assert!(view.get_global("entry").is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_max_call_depth() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func $leaf)
+            (func $middle
+                call $leaf
+                call $leaf)
+            (func (export "entry")
+                call $leaf
+                call $middle)
+            (func $even (export "even")
+                call $odd)
+            (func $odd
+                call $even)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let depth = |func| bin.max_call_depth(FunctionIndex::from_u32(func));
+
+    assert_eq!(depth(0)?, 1);
+    assert_eq!(depth(1)?, 2);
+    assert_eq!(depth(2)?, 3);
+    assert!(depth(3).is_err());
+    assert!(depth(5).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_table_initializers() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (table 4 funcref)
+            (func $first)
+            (func $second)
+            (elem (i32.const 1) $second $first)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let func = |index| Some(FunctionIndex::from_u32(index));
+
+    let slots = bin.table_initializers(0)?;
+    assert_eq!(slots, vec![None, func(1), func(0), None]);
+    assert!(bin.table_initializers(1).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_exception_check() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (export "plain") (result i32)
+                i32.const 1
+                i32.const 2
+                i32.add)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&ExceptionCheck)?;
+
+    // the parser doesn't enable exceptions, so feed the operators directly
+    let func = LocalFunctionIndex::from_u32(0);
+    let mut mid = Middleware::<binary::WasmBinary>::instrument(&ExceptionCheck, func)?;
+    let mut out = vec![];
+    mid.feed(Operator::Nop, &mut out)?;
+    assert!(mid
+        .feed(Operator::Throw { tag_index: 0 }, &mut out)
+        .is_err());
+    assert!(mid.feed(Operator::CatchAll, &mut out).is_err());
+    assert_eq!(out.len(), 1);
+    Ok(())
+}
+
+#[test]
+pub fn test_estimated_footprint() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1 3)
+            (data (i32.const 0) "hello")
+            (func (result i32)
+                i32.const 1
+                i32.const 2
+                i32.add)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+
+    // 4 opcodes (including the end), 5 bytes of data, and 3 pages of memory
+    let expected = 4 * ESTIMATED_BYTES_PER_OP + 5 + 3 * 65536;
+    assert_eq!(bin.estimated_footprint(), expected);
+
+    // an unbounded memory is clamped to 4GB
+    let wasm = as_wasm("(module (memory 1))");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.estimated_footprint(), 1 << 32);
+    Ok(())
+}
+
+#[test]
+pub fn test_set_start_function() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func $init)
+            (func $add (param i32 i32) (result i32)
+                local.get 0
+                local.get 1
+                i32.add)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.start, None);
+
+    bin.set_start_function(FunctionIndex::from_u32(0))?;
+    assert_eq!(bin.start, Some(0));
+
+    let add = FunctionIndex::from_u32(1);
+    let error = bin.set_start_function(add);
+    assert!(matches!(error, Err(ModuleModError::NonEmptyStart(func, _)) if func == add));
+    let missing = FunctionIndex::from_u32(2);
+    let error = bin.set_start_function(missing);
+    assert!(matches!(error, Err(ModuleModError::MissingFunction(func, _)) if func == missing));
+    assert_eq!(bin.start, Some(0));
+
+    bin.clear_start_function();
+    assert_eq!(bin.start, None);
+    Ok(())
+}
+
+#[test]
+pub fn test_imported_start_function() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "log" (func $log))
+            (func $init)
+            (start $log)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.move_start_function(STYLUS_START).is_err());
+    assert!(!bin.exports.contains_key(STYLUS_START));
+
+    let log = FunctionIndex::from_u32(0);
+    let error = bin.set_start_function(log);
+    assert_eq!(error, Err(ModuleModError::ImportedStart(log)));
+    bin.set_start_function(FunctionIndex::from_u32(1))?;
+    bin.move_start_function(STYLUS_START)?;
+    assert_eq!(bin.exports[STYLUS_START], (1, ExportKind::Func));
+    Ok(())
+}
+
+#[test]
+pub fn test_alignment_check() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (func (result i32)
+                i32.const 0
+                i32.load align=4
+                i32.const 2
+                i32.load align=2
+                i32.add)
+        )"#,
+    );
+    let mut bounds = CompileMemoryParams::default();
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&AlignmentCheck::new(bounds))?;
+
+    bounds.strict_alignment = true;
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.apply_middleware(&AlignmentCheck::new(bounds)).is_err());
+
+    let aligned = as_wasm("(module (memory 1) (func (drop (i32.load align=4 (i32.const 0)))))");
+    let mut bin = binary::parse(&aligned, Path::new("user"))?;
+    bin.apply_middleware(&AlignmentCheck::new(bounds))?;
+    Ok(())
+}
+
+#[test]
+pub fn test_abi_fingerprint() -> Result<()> {
+    let fingerprint = |body: &str, result: &str| -> Result<[u8; 32]> {
+        let wat = format!(
+            r#"
+            (module
+                (import "vm_hooks" "read_args" (func (param i32)))
+                (memory (export "memory") 1)
+                (global (export "counter") (mut i64) (i64.const 0))
+                (func (export "user_entrypoint") (param i32) (result {result})
+                    {body}))"#
+        );
+        let wasm = as_wasm(&wat);
+        Ok(binary::parse(&wasm, Path::new("user"))?.abi_fingerprint())
+    };
+
+    let first = fingerprint("i32.const 0", "i32")?;
+    let second = fingerprint("local.get 0 i32.const 1 i32.add", "i32")?;
+    let other = fingerprint("i64.const 0", "i64")?;
+    assert_eq!(first, second);
+    assert_ne!(first, other);
+    Ok(())
+}
+
+#[test]
+pub fn test_rename_import() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "read_args" (func (param i32)))
+            (import "vm_hooks" "write_result" (func (param i32 i32)))
+            (func)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.rename_import(FunctionIndex::from_u32(1), "host_v2", "set_result")?;
+
+    assert_eq!(bin.imports[0].module, "vm_hooks");
+    assert_eq!(bin.imports[1].module, "host_v2");
+    assert_eq!(bin.imports[1].name, "set_result");
+    assert!(bin.get_import("vm_hooks", "write_result").is_err());
+    assert!(bin.get_import("host_v2", "set_result").is_ok());
+
+    let local = FunctionIndex::from_u32(2);
+    assert!(bin.rename_import(local, "host_v2", "local").is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_unmetered_funcs() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func $helper (result i32)
+                i32.const 1)
+            (func $user (result i32)
+                i32.const 2)
+            (func $looping
+                (loop))
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 1;
+    pricing.unmetered_funcs.insert(0);
+
+    let meter = Meter::new(&pricing);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&meter)?;
+
+    let ink = meter.globals()[0].as_u32();
+    let decrements = |index: usize| {
+        let ops = bin.codes[index].expr.iter();
+        ops.filter(|op| matches!(op, Operator::GlobalSet { global_index } if *global_index == ink))
+            .count()
+    };
+    assert_eq!(decrements(0), 0);
+    assert_eq!(decrements(1), 1);
+
+    // tail calls are calls too, so exempt funcs may not make them either
+    for op in [
+        Operator::ReturnCall { function_index: 1 },
+        Operator::ReturnCallIndirect {
+            type_index: 0,
+            table_index: 0,
+        },
+    ] {
+        let mut bin = binary::parse(&wasm, Path::new("user"))?;
+        bin.codes[0].expr.insert(0, op);
+        assert!(bin.apply_middleware(&Meter::new(&pricing)).is_err());
+    }
+
+    // exempting a loop would allow unbounded execution
+    pricing.unmetered_funcs.insert(2);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.apply_middleware(&Meter::new(&pricing)).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_structurally_eq() -> Result<()> {
+    let module = |export: &str| {
+        as_wasm(&format!(
+            r#"
+            (module
+                (import "vm_hooks" "read_args" (func (param i32)))
+                (memory (export "memory") 1)
+                (table 2 funcref)
+                (global (mut i32) (i32.const 7))
+                (func (export "{export}") (param i32) (result i32)
+                    local.get 0))"#
+        ))
+    };
+    let wasm = module("entry");
+    let first = binary::parse(&wasm, Path::new("user"))?;
+    let second = binary::parse(&wasm, Path::new("user"))?;
+    assert!(first.structurally_eq(&second));
+
+    let wasm = module("renamed");
+    let other = binary::parse(&wasm, Path::new("user"))?;
+    assert!(!first.structurally_eq(&other));
+    Ok(())
+}
+
+#[test]
+pub fn test_wide_select_ink() -> Result<()> {
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 10;
+    pricing.wide_select_ink = Some(50);
+
+    // typed selects aren't enabled by the parser, so feed the operators directly
+    let charge = |ty| meter_charge(&pricing, Operator::TypedSelect { ty });
+    assert_eq!(charge(ValType::I32)?, 10 + 10);
+    assert_eq!(charge(ValType::I64)?, 50 + 10);
+    Ok(())
+}
+
+#[test]
+pub fn test_reference_ink() -> Result<()> {
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 10;
+    pricing.reference_ink = Some(70);
+    pricing.table_access_ink = Some(90);
+
+    // reference types aren't enabled by the parser, so feed the operators directly
+    let charge = |op| meter_charge(&pricing, op);
+    let ty = ValType::FuncRef;
+    assert_eq!(charge(Operator::RefFunc { function_index: 0 })?, 70 + 10);
+    assert_eq!(charge(Operator::RefNull { ty })?, 70 + 10);
+    assert_eq!(charge(Operator::TableGet { table: 0 })?, 90 + 10);
+    assert_eq!(charge(Operator::TableSet { table: 0 })?, 90 + 10);
+    assert_eq!(charge(Operator::Nop)?, 10 + 10);
+    Ok(())
+}
+
+#[test]
+pub fn test_heap_limit_bytes() -> Result<()> {
+    let wasm = as_wasm(r#"(module (memory (export "memory") 1 4))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.heap_limit_bytes()?, Some(4 * 65536));
+
+    let wasm = as_wasm(r#"(module (memory (export "memory") 1))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.heap_limit_bytes()?, None);
+
+    // a missing memory is an error rather than an unbounded heap
+    let wasm = as_wasm("(module)");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.heap_limit_bytes().is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_growable_pages() -> Result<()> {
+    let wasm = as_wasm(r#"(module (memory (export "memory") 3 10))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.growable_pages()?, Some(Pages(7)));
+
+    let wasm = as_wasm(r#"(module (memory (export "memory") 2 2))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.growable_pages()?, Some(Pages(0)));
+
+    let wasm = as_wasm(r#"(module (memory (export "memory") 1))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.growable_pages()?, None);
+
+    // a missing memory is an error rather than an unbounded heap
+    let wasm = as_wasm("(module)");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.growable_pages().is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_exactly_one_memory() -> Result<()> {
+    let wasm = as_wasm("(module)");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.apply_middleware(&ExactlyOneMemory).is_err());
+
+    let wasm = as_wasm("(module (memory 1))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&ExactlyOneMemory)?;
+
+    // the parser rejects multiple memories, so add one directly
+    let memory = bin.memories[0];
+    bin.memories.push(memory);
+    let err = bin.apply_middleware(&ExactlyOneMemory).unwrap_err();
+    assert!(err.to_string().contains('2'));
+    Ok(())
+}
+
+#[test]
+pub fn test_memory_size_global() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory (export "memory") 1 8)
+            (func (export "grow") (param i32) (result i32)
+                local.get 0
+                memory.grow
+                drop
+                memory.size)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&MemorySizeGlobal::default())?;
+
+    let mut machine = binary_machine(bin)?;
+    assert_eq!(machine.get_global(STYLUS_MEMORY_SIZE)?, Value::I32(1));
+
+    let size = machine.call_function("user", "grow", vec![Value::I32(2)])?;
+    assert_eq!(size, vec![Value::I32(3)]);
+    assert_eq!(machine.get_global(STYLUS_MEMORY_SIZE)?, Value::I32(3));
+
+    // a failed grow leaves the size unchanged
+    let size = machine.call_function("user", "grow", vec![Value::I32(100)])?;
+    assert_eq!(size, vec![Value::I32(3)]);
+    assert_eq!(machine.get_global(STYLUS_MEMORY_SIZE)?, Value::I32(3));
+    Ok(())
+}
+
+#[test]
+pub fn test_instrumentation_diff() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory (export "memory") 1 1)
+            (func $init)
+            (func (export "user_entrypoint") (param i32) (result i32)
+                i32.const 0)
+            (start $init)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let compile = CompileConfig::version(1, false);
+    let (_, diff) = bin.instrument_with_diff(&compile)?;
+
+    let added = |name: &str, kind| diff.added_exports.contains(&(name.to_owned(), kind));
+    assert!(added(STYLUS_INK_LEFT, ExportKind::Global));
+    assert!(added(STYLUS_INK_STATUS, ExportKind::Global));
+    assert!(added(STYLUS_START, ExportKind::Func));
+    assert!(diff.added_globals >= 2);
+    assert_eq!(diff.removed_start, Some(0));
+    assert!(diff.removed_exports.is_empty());
+    assert_eq!(diff.modified_funcs.len(), 2);
+    Ok(())
+}
+
+#[test]
+pub fn test_instrumentation_metrics() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory (export "memory") 1 1)
+            (func $init)
+            (func (export "user_entrypoint") (param i32) (result i32)
+                (block
+                    (br_if 0 (local.get 0)))
+                i32.const 0)
+            (start $init)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let compile = CompileConfig::version(1, false);
+    let mut metrics = HashMap::default();
+    let mut sink = |name: &str, value| {
+        metrics.insert(name.to_owned(), value);
+    };
+    bin.instrument_with_metrics(&compile, &mut sink)?;
+
+    // the entrypoint's br_if and two ends each close a block, as does the init's end
+    assert_eq!(metrics["ink checks"], 4);
+    assert_eq!(metrics["memory.grow sites"], 0);
+    Ok(())
+}
+
+#[test]
+pub fn test_distinct_signature_count() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (type (func (param i32) (result i32)))
+            (type (func))
+            (type (func (param i32) (result i32)))
+            (type (func (param i64)))
+            (type (func))
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.types.len(), 5);
+    assert_eq!(bin.distinct_signature_count()?, 3);
+    Ok(())
+}
+
+#[test]
+pub fn test_const_bound() -> Result<()> {
+    let wasm = as_wasm("(module (func (result i64) i64.const -5000))");
+    let mut bounds = CompileMemoryParams::default();
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&ConstBound::new(bounds))?;
+
+    bounds.max_const_magnitude = Some(5000);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&ConstBound::new(bounds))?;
+
+    bounds.max_const_magnitude = Some(4999);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.apply_middleware(&ConstBound::new(bounds)).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_is_leaf_function() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (type $t (func))
+            (table 1 funcref)
+            (func $leaf (param i32) (result i32)
+                local.get 0
+                i32.const 1
+                i32.add)
+            (func $caller (result i32)
+                i32.const 1
+                call $leaf)
+            (func $indirect
+                i32.const 0
+                call_indirect (type $t))
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let leaf = |func| bin.is_leaf_function(LocalFunctionIndex::from_u32(func));
+
+    assert!(leaf(0)?);
+    assert!(!leaf(1)?);
+    assert!(!leaf(2)?);
+    assert!(leaf(3).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_trap_sites() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (func (param i32 i32) (result i32)
+                local.get 0
+                local.get 1
+                i32.div_u
+                i32.load
+                i32.const 1
+                i32.add)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let sites = bin.trap_sites(LocalFunctionIndex::from_u32(0))?;
+    assert_eq!(sites, vec![(2, "division"), (3, "memory access")]);
+    Ok(())
+}
+
+#[test]
+pub fn test_move_start_function_forced() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func $init)
+            (func (export "stylus_start"))
+            (start $init)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.move_start_function(STYLUS_START).is_err());
+    assert!(bin.move_start_function_forced(STYLUS_START, false).is_err());
+    assert_eq!(bin.start, Some(0));
+
+    let replaced = bin.move_start_function_forced(STYLUS_START, true)?;
+    assert_eq!(replaced, Some(ExportKind::Func));
+    assert_eq!(bin.start, None);
+    assert_eq!(bin.exports[STYLUS_START], (0, ExportKind::Func));
+    Ok(())
+}
+
+#[test]
+pub fn test_call_fan_in() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "log" (func $log))
+            (func $helper)
+            (func $first
+                call $helper
+                call $helper)
+            (func $second
+                call $log
+                call $helper)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let fan_in = bin.call_fan_in()?;
+    let func = FunctionIndex::from_u32;
+
+    assert_eq!(fan_in[&func(0)], 1);
+    assert_eq!(fan_in[&func(1)], 3);
+    assert!(!fan_in.contains_key(&func(2)));
+    assert_eq!(fan_in.len(), 2);
+    Ok(())
+}
+
+#[test]
+pub fn test_max_ink_checks() -> Result<()> {
+    // each branch ends a basic block, requiring its own ink check
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (param i32)
+                (br_if 0 (local.get 0))
+                (br_if 0 (local.get 0))
+                (br_if 0 (local.get 0)))
+            (func (param i32)
+                (br_if 0 (local.get 0)))
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.max_ink_checks = Some(6);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&Meter::new(&pricing))?;
+
+    pricing.max_ink_checks = Some(5);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.apply_middleware(&Meter::new(&pricing)).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_global_check() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (global $counter (mut i32) (i32.const 0))
+            (func
+                (global.set $counter (i32.add (global.get $counter) (i32.const 1))))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&GlobalCheck::default())?;
+
+    // the parser rejects missing globals, so corrupt the module directly
+    bin.codes[0]
+        .expr
+        .insert(0, Operator::GlobalGet { global_index: 1 });
+    bin.codes[0].expr.insert(1, Operator::Drop);
+    assert!(bin.apply_middleware(&GlobalCheck::default()).is_err());
+
+    // injected globals count as existing
+    bin.add_global("injected", Type::I32, GlobalInit::I32Const(0))?;
+    bin.apply_middleware(&GlobalCheck::default())?;
+    Ok(())
+}
+
+#[test]
+pub fn test_summary_section() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "log" (func))
+            (memory (export "memory") 1 4)
+            (func)
+            (func)
+        )"#,
+    );
+    let compile = CompileConfig::version(1, false);
+    let summary = binary::parse(&wasm, Path::new("user"))?.summary(&compile)?;
+    assert_eq!(ModuleSummary::read_from(&wasm)?, None);
+    assert_eq!(summary.funcs, 3);
+    assert_eq!(summary.memory_max, Some(4));
+    assert_eq!(summary.pricing_version, 1);
+
+    for format in [ConfigFormat::Json, ConfigFormat::Bincode] {
+        let wasm = summary.append_to(&wasm, format)?;
+        assert_eq!(ModuleSummary::read_from(&wasm)?, Some(summary));
+
+        // the section doesn't otherwise affect the module
+        binary::parse(&wasm, Path::new("user"))?;
+    }
+    assert!(ModuleSummary::from_bytes(&[2]).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_uses_memory() -> Result<()> {
+    let compute = as_wasm(
+        r#"
+        (module
+            (memory (export "memory") 1)
+            (func (param i32) (result i32)
+                local.get 0
+                i32.const 1
+                i32.add)
+        )"#,
+    );
+    let load = as_wasm(
+        r#"
+        (module
+            (memory (export "memory") 1)
+            (func (param i32) (result i32)
+                local.get 0
+                i32.load)
+        )"#,
+    );
+    assert!(!binary::parse(&compute, Path::new("user"))?.uses_memory());
+    assert!(binary::parse(&load, Path::new("user"))?.uses_memory());
+    Ok(())
+}
+
+#[test]
+pub fn test_step_watchdog() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (export "long") (result i32)
+                i32.const 1
+                i32.const 2
+                i32.add
+                i32.const 3
+                i32.add
+                i32.const 4
+                i32.add
+                i32.const 5
+                i32.add
+                i32.const 6
+                i32.add)
+        )"#,
+    );
+
+    // the lone basic block has 11 operators, including the final end
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&StepWatchdog::new(11))?;
+    let mut machine = binary_machine(bin)?;
+    let output = machine.call_function("user", "long", vec![])?;
+    assert_eq!(output, vec![Value::I32(21)]);
+    assert_eq!(machine.get_global(STYLUS_STEPS)?, Value::I64(11));
+
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&StepWatchdog::new(10))?;
+    assert!(run_binary(bin, "long", vec![]).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_start_function_type() -> Result<()> {
+    let wasm = as_wasm("(module (func) (start 0))");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.start_function_type()?, Some(FunctionType::default()));
+
+    let wasm = as_wasm("(module (func))");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.start_function_type()?, None);
+    Ok(())
+}
+
+#[test]
+pub fn test_dead_functions() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "log" (func $log))
+            (table 1 funcref)
+            (elem (i32.const 0) $tabled)
+            (func $dead
+                call $helper)
+            (func $helper
+                call $log)
+            (func $tabled)
+            (func (export "entry"))
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let dead = bin.dead_functions()?;
+    assert_eq!(dead, vec![LocalFunctionIndex::from_u32(0)]);
+    Ok(())
+}
+
+#[test]
+pub fn test_strip_dead_functions() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "log" (func $log))
+            (func $dead (result i32)
+                call $orphan)
+            (func $orphan (result i32)
+                i32.const 1)
+            (func $helper (param i32) (result i32)
+                local.get 0
+                i32.const 2
+                i32.mul)
+            (func (export "entry") (param i32) (result i32)
+                local.get 0
+                call $helper)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&StripDeadFunctions)?;
+
+    // the orphan is only dead once its caller is removed
+    assert_eq!(bin.codes.len(), 2);
+    assert_eq!(bin.functions.len(), 2);
+    assert_eq!(bin.exports["entry"], (2, ExportKind::Func));
+    assert!(bin.dead_functions()?.is_empty());
+
+    let output = run_binary(bin, "entry", vec![Value::I32(21)])?;
+    assert_eq!(output, vec![Value::I32(42)]);
+    Ok(())
+}
+
+#[test]
+pub fn test_deterministic_random() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "insecure_random" (func $insecure (result i64)))
+            (import "vm_hooks" "seeded_random" (func $seeded (param i64) (result i64)))
+            (func (result i64)
+                call $insecure
+                call $insecure
+                i64.add)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&DeterministicRandom::default())?;
+
+    let counter = bin.get_global(STYLUS_RANDOM_COUNTER)?.as_u32();
+    let expr = &bin.codes[0].expr;
+    let calls: Vec<_> = expr
+        .iter()
+        .filter_map(|op| match op {
+            Operator::Call { function_index } => Some(*function_index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(calls, vec![1, 1]);
+
+    // each call passes the counter and then increments it
+    let increments = expr.windows(6).filter(|ops| {
+        use Operator::*;
+        matches!(ops, [
+            GlobalGet { global_index: a },
+            GlobalGet { global_index: b },
+            I64Const { value: 1 },
+            I64Add,
+            GlobalSet { global_index: c },
+            Call { function_index: 1 },
+        ] if [*a, *b, *c] == [counter; 3])
+    });
+    assert_eq!(increments.count(), 2);
+    assert_eq!(bin.globals[counter as usize], Value::I64(0));
+
+    // modules that don't ask for randomness are left alone
+    let wasm = as_wasm("(module (func))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&DeterministicRandom::default())?;
+    assert!(bin.get_global(STYLUS_RANDOM_COUNTER).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_block_count() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (param i32)
+                (block
+                    (loop
+                        (br_if 1 (local.get 0))
+                        (if (local.get 0)
+                            (then (block))
+                            (else nop))
+                        (br 0))))
+            (func)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let count = |func| bin.block_count(LocalFunctionIndex::from_u32(func));
+    assert_eq!(count(0)?, 4);
+    assert_eq!(count(1)?, 0);
+    assert!(count(2).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_max_grow_sites() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory (export "memory") 1 1)
+            (func (param i32)
+                (drop (memory.grow (local.get 0)))
+                (drop (memory.grow (local.get 0))))
+            (func (param i32)
+                (drop (memory.grow (local.get 0))))
+        )"#,
+    );
+    let mut bounds = CompileMemoryParams::default();
+    let instrument = |bounds| -> Result<()> {
+        let mut bin = binary::parse(&wasm, Path::new("user"))?;
+        bin.add_global(SCRATCH_GLOBAL, Type::I32, GlobalInit::I32Const(0))?;
+        bin.apply_middleware(&HeapBound::new(bounds))
+    };
+    instrument(bounds)?;
+
+    bounds.max_grow_sites = Some(3);
+    instrument(bounds)?;
+
+    bounds.max_grow_sites = Some(2);
+    assert!(instrument(bounds).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_call_graph() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "log" (func $log))
+            (type $t (func))
+            (table 1 funcref)
+            (func $leaf)
+            (func $middle
+                call $leaf
+                call $log
+                call $leaf)
+            (func $root
+                call $middle
+                i32.const 0
+                call_indirect (type $t))
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let graph = bin.call_graph()?;
+    let func = FunctionIndex::from_u32;
+
+    assert_eq!(graph.len(), 3);
+    assert!(graph[&func(1)].is_empty());
+    assert_eq!(graph[&func(2)], vec![func(1), func(0)]);
+    assert_eq!(graph[&func(3)], vec![func(2)]);
+    Ok(())
+}
+
+#[test]
+pub fn test_drop_elimination() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (global $g (mut i32) (i32.const 0))
+            (func $effect (result i32)
+                (global.set $g (i32.const 1))
+                i32.const 2)
+            (func (export "entry") (param i32) (result i32)
+                i32.const 5
+                drop
+                local.get 0
+                drop
+                call $effect
+                drop
+                local.get 0)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&DropElimination)?;
+
+    // only the call's result is still dropped
+    let expr = &bin.codes[1].expr;
+    assert_eq!(expr.len(), 4);
+    assert!(matches!(expr[0], Operator::Call { function_index: 0 }));
+    assert!(matches!(expr[1], Operator::Drop));
+    assert!(matches!(expr[2], Operator::LocalGet { local_index: 0 }));
+    assert!(matches!(expr[3], Operator::End));
+
+    let output = run_binary(bin, "entry", vec![Value::I32(7)])?;
+    assert_eq!(output, vec![Value::I32(7)]);
+    Ok(())
+}
+
+#[test]
+pub fn test_ensure_memory_export() -> Result<()> {
+    let wasm = as_wasm("(module (memory 1 4))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let error = bin.memory_info();
+    assert!(matches!(error, Err(ModuleModError::MissingMemory(name)) if name == "memory"));
+
+    bin.ensure_memory_export("memory")?;
+    assert_eq!(bin.exports["memory"], (0, ExportKind::Memory));
+    assert_eq!(bin.memory_info()?.max, Some(Pages(4)));
+    bin.ensure_memory_export("memory")?;
+
+    // the name must not already refer to something else
+    let wasm = as_wasm(r#"(module (memory 1) (func (export "memory")))"#);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.ensure_memory_export("memory").is_err());
+
+    // with two memories, it's unclear which to export
+    let wasm = as_wasm("(module (memory 1))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let memory = bin.memories[0];
+    bin.memories.push(memory);
+    assert!(bin.ensure_memory_export("memory").is_err());
+
+    let wasm = as_wasm("(module)");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.ensure_memory_export("memory").is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_data_overlaps() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (data (i32.const 0) "abcd")
+            (data (i32.const 4) "efgh")
+            (data (i32.const 6) "ij")
+            (data "passive")
+            (data (i32.const 0) "a")
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.data_overlaps()?, vec![(0, 4), (1, 2)]);
+    Ok(())
+}
+
+#[test]
+pub fn test_multi_memory_heap_bound() -> Result<()> {
+    let wasm = as_wasm(r#"(module (memory (export "memory") 2 2))"#);
+    let mut bounds = CompileMemoryParams::default();
+    bounds.heap_bound = Pages(4);
+
+    // the parser rejects multiple memories, so add them directly
+    let instrument = |bounds, second: u64| -> Result<()> {
+        let mut bin = binary::parse(&wasm, Path::new("user"))?;
+        bin.add_global(SCRATCH_GLOBAL, Type::I32, GlobalInit::I32Const(0))?;
+        let mut memory = bin.memories[0];
+        memory.initial = second;
+        memory.maximum = Some(second);
+        bin.memories.push(memory);
+        bin.apply_middleware(&HeapBound::new(bounds))
+    };
+    assert!(instrument(bounds, 1).is_err());
+
+    bounds.allow_multi_memory = true;
+    instrument(bounds, 4)?;
+
+    let err = instrument(bounds, 5).unwrap_err();
+    assert!(err.to_string().contains(&format!("memory {}", 1_u32.red())));
+    Ok(())
+}
+
+#[test]
+pub fn test_max_local_index() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (param i32 i32) (result i32)
+                (local i32 i32 i32)
+                (local.set 2 (local.get 0))
+                (local.set 3 (local.tee 4 (local.get 1)))
+                (local.get 2))
+            (func)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let max = |func| bin.max_local_index(LocalFunctionIndex::from_u32(func));
+    assert_eq!(max(0)?, 4);
+    assert_eq!(max(1)?, 0);
+    assert!(max(2).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_module_mod_errors() -> Result<()> {
+    use ModuleModError::*;
+
+    let wasm = as_wasm(r#"(module (global (export "g") i32 (i32.const 0)) (func))"#);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let error = bin.add_global("g", Type::I32, GlobalInit::I32Const(0));
+    assert_eq!(error, Err(DuplicateExport("g".to_owned())));
+
+    let reuse = OnGlobalCollision::Reuse;
+    let error = bin.add_global_as("g", Type::I64, GlobalInit::I64Const(0), reuse);
+    assert_eq!(error, Err(GlobalTypeMismatch("g".to_owned())));
+
+    let func = FunctionIndex::from_u32(1);
+    assert_eq!(bin.get_function(func), Err(MissingFunction(func, None)));
+
+    // a stale name is still reported
+    bin.names.functions.insert(1, "ghost".to_owned());
+    let error = bin.get_function(func);
+    assert_eq!(error, Err(MissingFunction(func, Some("ghost".to_owned()))));
+
+    let wasm = as_wasm(r#"(module (memory (export "memory") 2 2))"#);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.add_global(SCRATCH_GLOBAL, Type::I32, GlobalInit::I32Const(0))?;
+    let mut bounds = CompileMemoryParams::default();
+    bounds.heap_bound = Pages(1);
+
+    let error = bin.apply_middleware(&HeapBound::new(bounds)).unwrap_err();
+    let expected = MemoryMinimumExceedsLimit {
+        memory: 0,
+        minimum: Pages(2),
+        limit: Pages(1),
+    };
+    assert_eq!(error.downcast_ref::<ModuleModError>(), Some(&expected));
+    Ok(())
+}
+
+#[test]
+pub fn test_host_calls_only_metering() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "env" "host" (func $host))
+            (func $compute (param i32) (result i32)
+                (loop (br_if 0 (local.get 0)))
+                (i32.add (local.get 0) (i32.const 1)))
+            (func $calls
+                (call $host)
+                (drop (call $compute (i32.const 1)))
+                (call $host))
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 1;
+    pricing.granularity = MeterGranularity::HostCallsOnly;
+
+    let meter = Meter::new(&pricing);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&meter)?;
+
+    let ink = meter.globals()[0].as_u32();
+    let decrements = |index: usize| {
+        let ops = bin.codes[index].expr.iter();
+        ops.filter(|op| matches!(op, Operator::GlobalSet { global_index } if *global_index == ink))
+            .count()
+    };
+    assert_eq!(decrements(0), 0);
+    assert_eq!(decrements(1), 2);
+
+    // each host call is immediately preceded by its check
+    let code = &bin.codes[1].expr;
+    for (index, op) in code.iter().enumerate() {
+        if matches!(op, Operator::Call { function_index: 0 }) {
+            let prior = &code[index - 1];
+            assert!(matches!(prior, Operator::GlobalSet { global_index } if *global_index == ink));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+pub fn test_global_value() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (global (export "count") (mut i32) (i32.const 7))
+            (func (export "func"))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.global_value("count")?, Value::I32(7));
+
+    bin.set_global_value("count", Value::I32(8))?;
+    assert_eq!(bin.global_value("count")?, Value::I32(8));
+
+    assert!(bin.set_global_value("count", Value::I64(8)).is_err());
+    assert!(bin.global_value("func").is_err());
+    assert!(bin.global_value("missing").is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_control_balance_check() -> Result<()> {
+    use Operator::*;
+
+    let wasm = as_wasm("(module (func (block (nop))))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&ControlBalanceCheck)?;
+
+    let feed = |ops: Vec<Operator<'static>>| -> Result<()> {
+        let func = LocalFunctionIndex::from_u32(0);
+        let check = ControlBalanceCheck;
+        let mut mid = Middleware::<binary::WasmBinary>::instrument(&check, func)?;
+        let mut out = vec![];
+        for op in ops {
+            mid.feed(op, &mut out)?;
+        }
+        mid.finish()
+    };
+    let blockty = wasmparser::BlockType::Empty;
+    feed(vec![Block { blockty }, Loop { blockty }, End, End, End])?;
+
+    let err = feed(vec![Block { blockty }, End, End, End]).unwrap_err();
+    assert!(err.to_string().contains(&format!("func {}", 0_u32.red())));
+    assert!(feed(vec![Else, End]).is_err());
+
+    // streams that run out before the function's final end
+    let err = feed(vec![Block { blockty }, End]).unwrap_err();
+    assert!(err.to_string().contains(&format!("func {}", 0_u32.red())));
+    assert!(feed(vec![Block { blockty }, Loop { blockty }, End]).is_err());
+    assert!(feed(vec![]).is_err());
+
+    let func = &mut bin.codes[0].expr;
+    func.pop();
+    let err = bin.apply_middleware(&ControlBalanceCheck).unwrap_err();
+    assert!(format!("{err:?}").contains("unclosed"));
+    Ok(())
+}
+
+#[test]
+pub fn test_function_costs() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "env" "host" (func))
+            (func (result i32)
+                i32.const 1)
+            (func
+                (loop (br 0)))
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 1;
+    pricing.ink_header_cost = 100;
+
+    let meter = Meter::new(&pricing);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&meter)?;
+
+    // loops are counted once, and ink checks aren't counted at all
+    let costs = meter.function_costs();
+    assert_eq!(costs.len(), 2);
+    assert_eq!(costs[&FunctionIndex::from_u32(1)], 2);
+    assert_eq!(costs[&FunctionIndex::from_u32(2)], 4);
+    Ok(())
+}
+
+#[test]
+pub fn test_num_defined_functions() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "env" "a" (func))
+            (import "env" "b" (func))
+            (func)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.func_import_count(), 2);
+    assert_eq!(bin.num_defined_functions(), 1);
+    Ok(())
+}
+
+#[test]
+pub fn test_opcode_pricing() -> Result<()> {
+    use OperatorCategory::*;
+
+    let category = |op: Operator| op.code().category();
+    assert_eq!(category(Operator::I32Add), Arithmetic);
+    assert_eq!(category(Operator::End), ControlFlow);
+    assert_eq!(category(Operator::Call { function_index: 0 }), Call);
+    assert_eq!(category(Operator::LocalGet { local_index: 0 }), Other);
+
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 10;
+    assert_eq!(meter_charge(&pricing, Operator::I32Add)?, 10 + 10);
+
+    pricing.opcode_pricing = OpcodePricing::default().with_category(Arithmetic, 30);
+    assert_eq!(meter_charge(&pricing, Operator::I32Add)?, 30 + 10);
+    assert_eq!(meter_charge(&pricing, Operator::Nop)?, 10 + 10);
+
+    // unpriced categories, like the end of the function, use the default
+    pricing.opcode_pricing = pricing.opcode_pricing.with_default(5);
+    assert_eq!(meter_charge(&pricing, Operator::I32Add)?, 30 + 5);
+    assert_eq!(meter_charge(&pricing, Operator::Nop)?, 5 + 5);
+    Ok(())
+}
+
+#[test]
+pub fn test_canonicalize_exports() -> Result<()> {
+    let names = ["e", "b", "a", "d", "f", "c", "h", "g"];
+    let exports = |names: &[&str]| -> Result<Vec<String>> {
+        let exports: String = names
+            .iter()
+            .map(|name| format!(r#"(export "{name}" (func 0))"#))
+            .collect();
+        let wasm = as_wasm(&format!("(module (func) {exports})"));
+        let mut bin = binary::parse(&wasm, Path::new("user"))?;
+        bin.apply_middleware(&CanonicalizeExports)?;
+        Ok(bin.exports.keys().cloned().collect())
+    };
+    let mut reversed = names;
+    reversed.reverse();
+
+    let mut sorted = names.map(str::to_owned).to_vec();
+    sorted.sort();
+
+    let canonical = exports(&names)?;
+    assert_eq!(canonical, sorted);
+    assert_eq!(canonical, exports(&reversed)?);
+    Ok(())
+}
+
+#[test]
+pub fn test_import_allowlist() -> Result<()> {
+    use crate::value::ArbValueType::*;
+
+    let mut allowed = HashMap::default();
+    let read_args = ("vm_hooks".to_owned(), "read_args".to_owned());
+    allowed.insert(read_args, FunctionType::new([I32], []));
+    let allowlist = ImportAllowlist::new(allowed);
+
+    let check = |import: &str| -> Result<()> {
+        let wasm = as_wasm(&format!("(module {import})"));
+        let mut bin = binary::parse(&wasm, Path::new("user"))?;
+        bin.apply_middleware(&allowlist)
+    };
+    check(r#"(import "vm_hooks" "read_args" (func (param i32)))"#)?;
+    assert!(check(r#"(import "vm_hooks" "read_args" (func (param i64)))"#).is_err());
+    assert!(check(r#"(import "vm_hooks" "exit_early" (func (param i32)))"#).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_start_function_cost() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func)
+            (func $start
+                (drop (i32.const 1)))
+            (start $start)
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |op, _| match op {
+        Operator::Drop => 5,
+        _ => 2,
+    };
+    pricing.ink_header_cost = 100;
+
+    let meter = Meter::new(&pricing);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&meter)?;
+
+    // i32.const, drop, and end
+    assert_eq!(meter.start_function_cost()?, Some(2 + 5 + 2));
+
+    let wasm = as_wasm("(module (func))");
+    let meter = Meter::new(&pricing);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&meter)?;
+    assert_eq!(meter.start_function_cost()?, None);
+    Ok(())
+}
+
+#[test]
+pub fn test_non_float() -> Result<()> {
+    let check = |body: &str| -> Result<()> {
+        let wasm = as_wasm(&format!("(module {body})"));
+        let mut bin = binary::parse(&wasm, Path::new("user"))?;
+        bin.apply_middleware(&NonFloat)
+    };
+    check("(func (result i64) (i64.add (i64.const 1) (i64.const 2)))")?;
+
+    let err = check("(func (result f64) (f64.add (f64.const 1) (f64.const 2)))").unwrap_err();
+    assert!(err.to_string().contains(&format!("func {}", 0_u32.red())));
+    assert!(check("(func (result i32) (i32.trunc_f32_s (f32.const 1)))").is_err());
+    assert!(check("(global f32 (f32.const 0))").is_err());
+    assert!(check("(func (param f64))").is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_remove_global_and_rename_export() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (global (export "used") (mut i32) (i32.const 0))
+            (func (export "func") (result i32)
+                global.get 0)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.add_global("unused", Type::I64, GlobalInit::I64Const(0))?;
+    assert_eq!(bin.global_count(), 2);
+
+    // the last global is dropped when unused, while others linger unexported
+    bin.remove_global("unused")?;
+    assert_eq!(bin.global_count(), 1);
+    bin.remove_global("used")?;
+    assert_eq!(bin.global_count(), 1);
+    assert!(bin.exports.get("used").is_none());
+    let missing = ModuleModError::MissingGlobal("func".to_owned());
+    assert_eq!(bin.remove_global("func"), Err(missing));
+
+    // a global exported under another name stays until its last export is removed
+    let twice = bin.add_global("twice", Type::I32, GlobalInit::I32Const(0))?;
+    let alias = (twice.as_u32(), ExportKind::Global);
+    bin.exports.insert("alias".to_owned(), alias);
+    bin.remove_global("twice")?;
+    assert_eq!(bin.global_count(), 2);
+    assert_eq!(bin.get_global("alias")?, twice);
+    bin.remove_global("alias")?;
+    assert_eq!(bin.global_count(), 1);
+
+    bin.rename_export("func", "main")?;
+    assert_eq!(bin.exports.get("main"), Some(&(0, ExportKind::Func)));
+    assert!(bin.exports.get("func").is_none());
+
+    bin.add_global("taken", Type::I32, GlobalInit::I32Const(0))?;
+    let taken = ModuleModError::DuplicateExport("taken".to_owned());
+    assert_eq!(bin.rename_export("main", "taken"), Err(taken));
+    let missing = ModuleModError::MissingExport("missing".to_owned());
+    assert_eq!(bin.rename_export("missing", "other"), Err(missing));
+    Ok(())
+}
+
+#[test]
+pub fn test_passive_element_bound() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (table 1 funcref)
+            (func)
+            (elem (i32.const 0) func 0)
+            (elem func 0 0 0)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.passive_element_count(), 3);
+
+    bin.apply_middleware(&PassiveElementBound::new(3))?;
+    assert!(bin.apply_middleware(&PassiveElementBound::new(2)).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_uses_reference_types() -> Result<()> {
+    let wasm = as_wasm("(module (table 1 funcref) (func (local i32)))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(!bin.uses_reference_types());
+
+    // the parser rejects reference types, so add the local directly
+    let externref = ValType::ExternRef.try_into()?;
+    bin.codes[0].locals.push(Local {
+        index: 1,
+        value: externref,
+    });
+    assert!(bin.uses_reference_types());
+    Ok(())
+}
+
+#[test]
+pub fn test_module_limits() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "vm_hooks" "msg_value" (func (param i32)))
+            (global i32 (i32.const 0))
+            (func (export "a"))
+            (func (export "b"))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+
+    let mut bounds = CompileMemoryParams::default();
+    bin.apply_middleware(&ModuleLimits::new(bounds))?;
+
+    bounds.max_functions = Some(2);
+    bounds.max_imports = Some(1);
+    bounds.max_globals = Some(1);
+    bounds.max_exports = Some(2);
+    bin.apply_middleware(&ModuleLimits::new(bounds))?;
+
+    let tighten: [fn(&mut CompileMemoryParams); 4] = [
+        |b| b.max_functions = Some(1),
+        |b| b.max_imports = Some(0),
+        |b| b.max_globals = Some(0),
+        |b| b.max_exports = Some(1),
+    ];
+    for tighten in tighten {
+        let mut bounds = bounds;
+        tighten(&mut bounds);
+        assert!(bin.apply_middleware(&ModuleLimits::new(bounds)).is_err());
+    }
+    Ok(())
+}
+
+#[test]
+pub fn test_return_value_ink() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (result i32 i64)
+                i32.const 0
+                i64.const 0)
+            (func
+                (block
+                    br 0))
+            (func (result i32 i64)
+                (block
+                    i32.const 0
+                    i64.const 0
+                    return)
+                unreachable)
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 1;
+    pricing.return_value_ink = 10;
+
+    let meter = Meter::new(&pricing);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&meter)?;
+
+    // only the final end returns, not those of nested blocks
+    let costs = meter.function_costs();
+    assert_eq!(costs[&FunctionIndex::from_u32(0)], 3 + 2 * 10);
+    assert_eq!(costs[&FunctionIndex::from_u32(1)], 4);
+
+    // the explicit return and the unreachable final end each charge
+    assert_eq!(costs[&FunctionIndex::from_u32(2)], 7 + 2 * 2 * 10);
+    Ok(())
+}
+
+#[test]
+pub fn test_memory_accesses() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (func
+                i32.const 0
+                i32.const 0
+                i32.load offset=8
+                drop
+                i64.const 0
+                i64.store offset=16
+                i32.const 0
+                i32.load8_u
+                drop)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let func = LocalFunctionIndex::from_u32(0);
+    assert_eq!(bin.memory_accesses(func)?, vec![(8, 4), (16, 8), (0, 1)]);
+
+    let missing = LocalFunctionIndex::from_u32(1);
+    assert!(bin.memory_accesses(missing).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_memory_grow_ink() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 0 4)
+            (func (export "grow") (param i32) (result i32)
+                local.get 0
+                memory.grow)
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 0;
+    pricing.ink_header_cost = 0;
+    pricing.memory_grow_ink = 10;
+
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&Meter::new(&pricing))?;
+    bin.apply_middleware(&DynamicMeter::new(&pricing))?;
+    let mut machine = binary_machine(bin)?;
+    machine.set_global(STYLUS_INK_LEFT, Value::I64(100))?;
+
+    let output = machine.call_function("user", "grow", vec![Value::I32(3)])?;
+    assert_eq!(output, vec![Value::I32(0)]);
+    assert_eq!(machine.get_global(STYLUS_INK_LEFT)?, Value::I64(70));
+
+    // pages are paid for even when the grow fails
+    let output = machine.call_function("user", "grow", vec![Value::I32(2)])?;
+    assert_eq!(output, vec![Value::I32(u32::MAX)]);
+    assert_eq!(machine.get_global(STYLUS_INK_LEFT)?, Value::I64(50));
+
+    let error = machine.call_function("user", "grow", vec![Value::I32(6)]);
+    assert!(error.is_err());
+    assert_eq!(machine.get_global(STYLUS_INK_STATUS)?, Value::I32(1));
+    Ok(())
+}
+
+#[test]
+pub fn test_heap_bound_rounding() {
+    let mut bounds = CompileMemoryParams::default();
+    let page = WASM_PAGE_SIZE as u64;
+
+    bounds.set_heap_bound_bytes(3 * page + 1, PageRounding::Down);
+    assert_eq!(bounds.heap_bound, Pages(3));
+    bounds.set_heap_bound_bytes(3 * page + 1, PageRounding::Up);
+    assert_eq!(bounds.heap_bound, Pages(4));
+
+    // exact multiples aren't rounded either way
+    for rounding in [PageRounding::Down, PageRounding::Up] {
+        bounds.set_heap_bound_bytes(3 * page, rounding);
+        assert_eq!(bounds.heap_bound, Pages(3));
+    }
+
+    bounds.set_heap_bound_bytes(u64::MAX, PageRounding::Up);
+    assert_eq!(bounds.heap_bound, Pages(WASM_MAX_PAGES));
+}
+
+#[test]
+pub fn test_custom_sections() -> Result<()> {
+    let mut wasm = as_wasm(r#"(module $user (func $entry))"#);
+
+    // custom sections have id 0, followed by a sized name and payload
+    wasm.extend([0, 8, 6]);
+    wasm.extend(b".debug");
+    wasm.push(0xff);
+
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.custom_section(".debug"), Some(&[0xff][..]));
+    assert!(bin.custom_section("name").is_some());
+    assert!(bin.custom_section("missing").is_none());
+
+    bin.strip_custom_sections(&["name"]);
+    assert!(bin.custom_section(".debug").is_none());
+    assert!(bin.custom_section("name").is_some());
+
+    bin.strip_custom_sections(&[]);
+    assert!(bin.customs.is_empty());
+    assert_eq!(bin.names.module, "user");
+    assert_eq!(bin.names.functions[&0], "entry");
+    Ok(())
+}
+
+#[test]
+pub fn test_operator_categories() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "env" "host" (func $host (param f32)))
+            (memory 1)
+            (func (param i32)
+                (if (local.get 0)
+                    (then
+                        (call $host (f32.convert_i32_u (i32.load (i32.const 0))))))
+                (i32.store (i32.const 0) (i32.add (local.get 0) (i32.const 1))))
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let counts = bin.operator_categories(LocalFunctionIndex::from_u32(0))?;
+
+    let expected = [
+        ("arithmetic", 1),
+        ("memory", 2),
+        ("control", 3), // the if, its end, and the function's
+        ("call", 1),
+        ("const", 3),
+        ("conversion", 1),
+        ("other", 2),
+    ];
+    assert_eq!(counts, expected.into_iter().collect());
+    Ok(())
+}
+
+#[test]
+pub fn test_move_start_signature() -> Result<()> {
+    let wasm = as_wasm("(module (func) (func (param i32)))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+
+    // the spec forbids this, so parsing would reject it
+    bin.start = Some(1);
+    let func = FunctionIndex::from_u32(1);
+    let ty = FunctionType::new(vec![ArbValueType::I32], vec![]);
+    let error = bin.move_start_function(STYLUS_START);
+    assert_eq!(error, Err(ModuleModError::NonEmptyStart(func, ty)));
+    assert_eq!(bin.start, Some(1));
+
+    bin.start = Some(0);
+    bin.move_start_function(STYLUS_START)?;
+    assert_eq!(bin.exports[STYLUS_START], (0, ExportKind::Func));
+    Ok(())
+}
+
+#[test]
+pub fn test_export_prologue() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "env" "host" (func))
+            (func $helper)
+            (func $entry (export "entry")
+                call $helper)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(!bin.is_function_exported(FunctionIndex::from_u32(1)));
+    assert!(bin.is_function_exported(FunctionIndex::from_u32(2)));
+
+    bin.apply_middleware(&ExportPrologue::new(vec![Operator::Nop]))?;
+    let helper = &bin.codes[0].expr;
+    assert_eq!(helper.len(), 1);
+    assert!(matches!(helper[0], Operator::End));
+
+    let entry = &bin.codes[1].expr;
+    assert_eq!(entry.len(), 3);
+    assert!(matches!(entry[0], Operator::Nop));
+    assert!(matches!(entry[1], Operator::Call { function_index: 1 }));
+    assert!(matches!(entry[2], Operator::End));
+    Ok(())
+}
+
+#[test]
+pub fn test_start_hooks() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (global $g (export "g") (mut i32) (i32.const 0))
+            (func $start
+                (global.set $g (i32.add (global.get $g) (i32.const 1))))
+            (start $start)
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+
+    let seed = vec![("seed".to_owned(), Type::I32, GlobalInit::I32Const(0))];
+    let hooks = StartHooks::new().hook(
+        "seed",
+        seed.clone(),
+        Box::new(|globals: &[GlobalIndex]| {
+            vec![
+                Operator::I32Const { value: 10 },
+                Operator::GlobalSet { global_index: 0 },
+                Operator::I32Const { value: 7 },
+                Operator::GlobalSet {
+                    global_index: globals[0].as_u32(),
+                },
+            ]
+        }),
+    );
+    assert!(hooks.apply(&mut bin, "g").is_err());
+    hooks.apply(&mut bin, "init")?;
+    assert_eq!(bin.start, None);
+
+    // re-registering a global names the hook responsible
+    let again = StartHooks::new().hook("again", seed, Box::new(|_: &[GlobalIndex]| vec![]));
+    let error = again.apply(&mut bin, "init_again").unwrap_err();
+    assert!(error.to_string().contains("again"));
+
+    // the hook runs before the original start
+    let mut machine = binary_machine(bin)?;
+    machine.call_function("user", "init", vec![])?;
+    assert_eq!(machine.get_global("g")?, Value::I32(11));
+    assert_eq!(machine.get_global("seed")?, Value::I32(7));
+    Ok(())
+}
+
+#[test]
+pub fn test_has_passive_data() -> Result<()> {
+    let wasm = as_wasm(r#"(module (memory 1) (data (i32.const 0) "active"))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(!bin.has_passive_data());
+
+    let wasm = as_wasm(r#"(module (memory 1) (data (i32.const 0) "active") (data "passive"))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert!(bin.has_passive_data());
+    Ok(())
+}
+
+#[test]
+pub fn test_functions() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (import "env" "host" (func (param i64)))
+            (func (result i32)
+                i32.const 0)
+            (func (param i32) (result i32)
+                local.get 0)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.function_count(), 3);
+
+    let mut funcs = vec![];
+    for (func, ty) in bin.functions() {
+        funcs.push((func.as_u32(), ty?));
+    }
+    use ArbValueType::*;
+    let expected = vec![
+        (0, FunctionType::new(vec![I64], vec![])),
+        (1, FunctionType::new(vec![], vec![I32])),
+        (2, FunctionType::new(vec![I32], vec![I32])),
+    ];
+    assert_eq!(funcs, expected);
+    Ok(())
+}
+
+#[test]
+pub fn test_bulk_memory_ink() -> Result<()> {
+    let mut pricing = CompilePricingParams::default();
+    pricing.costs = |_, _| 0;
+    pricing.ink_header_cost = 0;
+    pricing.memory_fill_ink = 2;
+
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1 1)
+            (func (export "fill") (param i32)
+                (memory.fill (i32.const 0) (i32.const 0) (local.get 0)))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&Meter::new(&pricing))?;
+    bin.apply_middleware(&DynamicMeter::new(&pricing))?;
+
+    let mut machine = binary_machine(bin)?;
+    let mut fill = |bytes: u32| -> Result<u64> {
+        machine.set_global(STYLUS_INK_LEFT, Value::I64(10_000))?;
+        machine.call_function("user", "fill", vec![Value::I32(bytes)])?;
+        let ink: u64 = machine.get_global(STYLUS_INK_LEFT)?.try_into()?;
+        Ok(10_000 - ink)
+    };
+    assert_eq!(fill(10)?, 20);
+    assert_eq!(fill(1000)?, 2000);
+
+    // the prover can't run memory.init, so it's rejected
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1 1)
+            (data "passive")
+            (func
+                (memory.init 0 (i32.const 0) (i32.const 0) (i32.const 7)))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&Meter::new(&pricing))?;
+    assert!(bin.apply_middleware(&DynamicMeter::new(&pricing)).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_dry_run() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func $start)
+            (start $start)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+
+    let meter = Meter::new(&CompilePricingParams::default());
+    let diff = bin.dry_run(&meter)?;
+    let (ink, status) = (STYLUS_INK_LEFT.to_owned(), STYLUS_INK_STATUS.to_owned());
+    let expected = vec![
+        (ink, ArbValueType::I64, Value::I64(0)),
+        (status, ArbValueType::I32, Value::I32(0)),
+    ];
+    assert_eq!(diff.added_globals, expected);
+    assert_eq!(diff.moved_start, None);
+    assert!(bin.globals.is_empty());
+
+    let diff = bin.dry_run(&StartMover::default())?;
+    assert_eq!(diff.moved_start, Some((0, STYLUS_START.to_owned())));
+    assert!(diff.added_globals.is_empty() && diff.renamed_exports.is_empty());
+    assert_eq!(bin.start, Some(0));
+    Ok(())
+}
+
+#[test]
+pub fn test_check_required_exports() -> Result<()> {
+    let wasm = as_wasm(r#"(module (memory (export "memory") 1) (func (export "entry")))"#);
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.check_required_exports(&[("memory", ExportKind::Memory), ("entry", ExportKind::Func)])?;
+
+    // every problem is reported, not just the first
+    let required = [
+        ("memory", ExportKind::Memory),
+        ("entry", ExportKind::Global),
+        ("user_entrypoint", ExportKind::Func),
+    ];
+    let error = bin.check_required_exports(&required).unwrap_err();
+    let error = error.to_string();
+    assert!(!error.contains("memory"));
+    assert!(error.contains("is a Func, not a Global"));
+    assert!(error.contains("user_entrypoint".red().as_str()));
+    Ok(())
+}
+
+#[test]
+pub fn test_module_max_stack_height() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (result i32)
+                (i32.add (i32.const 1) (i32.const 2)))
+            (func (result i32)
+                (i32.add (i32.const 1) (i32.add (i32.const 2) (i32.const 3))))
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    let height = |func| bin.max_stack_height(LocalFunctionIndex::from_u32(func));
+    assert_eq!(height(0)?, 2);
+    assert_eq!(height(1)?, 3);
+    assert!(height(2).is_err());
+    assert_eq!(bin.module_max_stack_height()?, 3);
+
+    let wasm = as_wasm("(module)");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.module_max_stack_height()?, 0);
+    Ok(())
+}
+
+#[test]
+pub fn test_locals_bound() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (param i32 i32) (local i32 i32))
+            (func (local i64 i64 i64))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&LocalsBound::new(3))?;
+
+    // params don't count, and the offending function is named
+    let error = bin.apply_middleware(&LocalsBound::new(2)).unwrap_err();
+    let func = format!("func {}", 1.red());
+    assert!(format!("{error:?}").contains(&func));
+
+    let mut compile = CompileConfig::version(1, false);
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 0 0)
+            (export "memory" (memory 0))
+            (func (export "user_entrypoint") (param i32) (result i32)
+                (local i32 i32 i32)
+                (i32.const 0))
+        )"#,
+    );
+    let mut instrument = |limit| {
+        compile.bounds.max_locals_per_function = Some(limit);
+        binary::parse(&wasm, Path::new("user"))?.instrument(&compile)
+    };
+    instrument(3)?;
+    assert!(instrument(2).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_empty_function_metering() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func)
+            (func
+                (drop (i32.const 1)))
+            (func (local i32))
+        )"#,
+    );
+    let mut pricing = CompilePricingParams::default();
+    pricing.ink_header_cost = 100;
+    pricing.local_ink = 10;
+
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&Meter::new(&pricing))?;
+    let checked = |func: usize| {
+        let expr = &bin.codes[func].expr;
+        expr.iter().any(|op| matches!(op, Operator::I64Sub))
+    };
+
+    // only the empty function is free, since the last pays for its frame
+    assert!(matches!(bin.codes[0].expr[..], [Operator::End]));
+    assert!(checked(1));
+    assert!(checked(2));
+    Ok(())
+}
+
+#[test]
+pub fn test_table_maximum() -> Result<()> {
+    let wasm = as_wasm("(module (table 4 100 funcref))");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.table_maximum(0)?, Some(100));
+    assert!(bin.table_maximum(1).is_err());
+
+    let wasm = as_wasm("(module (table 4 funcref))");
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.table_maximum(0)?, None);
+    Ok(())
+}
+
+#[test]
+pub fn test_feature_check() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (table 1 funcref)
+            (func)
+        )"#,
+    );
+    let mvp = AllowedProposals {
+        reference_types: false,
+    };
+
+    // funcref tables predate the proposal
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&FeatureCheck::new(mvp))?;
+
+    // the parser rejects reference types, so inject an operator directly
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let size = [Operator::TableSize { table: 0 }, Operator::Drop];
+    bin.codes[0].expr.splice(0..0, size);
+    let error = bin.apply_middleware(&FeatureCheck::new(mvp)).unwrap_err();
+    let op = format!("{}", Operator::TableSize { table: 0 }.code().red());
+    assert!(format!("{error:?}").contains(&op));
+
+    // by default nothing is rejected
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let size = [Operator::TableSize { table: 0 }, Operator::Drop];
+    bin.codes[0].expr.splice(0..0, size);
+    bin.apply_middleware(&FeatureCheck::new(AllowedProposals::default()))?;
+    Ok(())
+}
+
+#[test]
+pub fn test_global_registry() -> Result<()> {
+    let wasm = as_wasm("(module)");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let registry = GlobalRegistry::new();
+    let (ty, init) = (Type::I64, GlobalInit::I64Const(0));
+
+    let shared = registry.clone();
+    shared.add_global(&mut bin, "meter", "stylus_shared", ty, init)?;
+    assert_eq!(registry.owner("stylus_shared"), Some("meter"));
+    registry.add_global(&mut bin, "watchdog", "stylus_other", ty, init)?;
+
+    // the diagnostic names both passes
+    let error = registry
+        .add_global(&mut bin, "watchdog", "stylus_shared", ty, init)
+        .unwrap_err();
+    let expected = format!(
+        "{} cannot add global {}: already added by {}",
+        "watchdog".red(),
+        "stylus_shared".red(),
+        "meter".red()
+    );
+    assert_eq!(error.to_string(), expected);
+    Ok(())
+}
+
+#[test]
+pub fn test_limit_heap() -> Result<()> {
+    let wasm = as_wasm(r#"(module (memory (export "memory") 1 8))"#);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.limit_heap(Pages(4))?, vec![Pages(4)]);
+    assert_eq!(bin.memory_maximum(0)?, Some(Pages(4)));
+
+    // the module's own maximum wins when lower
+    assert_eq!(bin.limit_heap(Pages(16))?, vec![Pages(4)]);
+
+    let wasm = as_wasm(r#"(module (memory (export "memory") 2))"#);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    assert_eq!(bin.limit_heap(Pages(3))?, vec![Pages(3)]);
+
+    let expected = ModuleModError::MemoryMinimumExceedsLimit {
+        memory: 0,
+        minimum: Pages(2),
+        limit: Pages(1),
+    };
+    assert_eq!(bin.limit_heap(Pages(1)), Err(expected));
+
+    // the parser rejects multiple memories, so add one directly
+    let wasm = as_wasm(r#"(module (memory (export "memory") 1))"#);
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let mut memory = bin.memories[0];
+    memory.initial = 6;
+    bin.memories.push(memory);
+
+    // the second memory fails, which mustn't leave the first clamped
+    assert!(bin.limit_heap(Pages(4)).is_err());
+    assert_eq!(bin.memory_maximum(0)?, None);
+    Ok(())
+}
+
+#[test]
+pub fn test_control_flow_ratio() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (func (param i32)
+                (block
+                    (br_if 0 (local.get 0))
+                    (drop (i32.const 1))))
+            (func)
+        )"#,
+    );
+    let bin = binary::parse(&wasm, Path::new("user"))?;
+
+    // block, local.get, br_if, i32.const, drop, end, end
+    let ratio = |func| bin.control_flow_ratio(LocalFunctionIndex::from_u32(func));
+    assert_eq!(ratio(0)?, 2. / 7.);
+    assert_eq!(ratio(1)?, 0.);
+    assert!(ratio(2).is_err());
+    Ok(())
+}
+
+#[test]
+pub fn test_data_segment_limits() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (data (i32.const 0) "abcd")
+            (data "ef")
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+
+    // only the passive segment counts, as with native builds
+    assert_eq!(bin.passive_data_count(), 1);
+    assert_eq!(bin.passive_data_bytes(), 2);
+
+    let mut bounds = CompileMemoryParams::default();
+    let mut check = |bounds| bin.apply_middleware(&ModuleLimits::new(bounds));
+    bounds.max_data_segments = Some(1);
+    bounds.max_data_bytes = Some(2);
+    check(bounds)?;
+
+    bounds.max_data_segments = Some(0);
+    let error = check(bounds).unwrap_err().to_string();
+    assert!(error.contains(&format!("{} passive data segments", 1.red())));
+
+    bounds.max_data_segments = None;
+    bounds.max_data_bytes = Some(1);
+    let error = check(bounds).unwrap_err().to_string();
+    assert!(error.contains(&format!("{} bytes", 2.red())));
+    Ok(())
+}
+
+#[test]
+pub fn test_at_most_one_table() -> Result<()> {
+    let wasm = as_wasm("(module)");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&AtMostOneTable)?;
+
+    let wasm = as_wasm("(module (table 1 funcref))");
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    bin.apply_middleware(&AtMostOneTable)?;
+
+    // the parser rejects multiple tables, so add one directly
+    let table = bin.tables[0];
+    bin.tables.push(table);
+    let err = bin.apply_middleware(&AtMostOneTable).unwrap_err();
+    assert!(err.to_string().contains(&2.red().to_string()));
+    Ok(())
+}
+
+#[test]
+pub fn test_profiler() -> Result<()> {
+    let wasm = as_wasm(
+        r#"
+        (module
+            (memory 1)
+            (func $add (param i32 i32) (result i32)
+                (i32.add (local.get 0) (local.get 1)))
+            (func (param i32)
+                (drop (call $add (i32.load (local.get 0)) (i32.const 1)))
+                (if (local.get 0)
+                    (then (drop (select (i32.const 1) (i32.const 2) (local.get 0)))))
+                (return))
+        )"#,
+    );
+    let mut bin = binary::parse(&wasm, Path::new("user"))?;
+    let exprs: Vec<_> = bin.codes.iter().map(|code| code.expr.clone()).collect();
+
+    let profiler = Profiler::new();
+    bin.apply_middleware(&profiler)?;
+    let expected = OpcodeHistogram {
+        control: 5,    // if, end (if), return, end, end
+        memory: 1,     // i32.load
+        numeric: 4,    // i32.add, i32.const x3
+        call: 1,       // call
+        parametric: 3, // drop x2, select
+        other: 5,      // local.get x5
+    };
+    assert_eq!(profiler.summary(), expected);
+    assert_eq!(profiler.summary().total(), 19);
+
+    // operators pass through unchanged
+    for (code, expr) in bin.codes.iter().zip(exprs) {
+        assert_eq!(format!("{:?}", code.expr), format!("{expr:?}"));
+    }
+    Ok(())
+}
+
 #[test]
 pub fn test_compress() -> Result<()> {
     let data = include_bytes!("../../../target/machines/latest/forward_stub.wasm");
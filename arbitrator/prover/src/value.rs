@@ -397,7 +397,7 @@ impl TryInto<u64> for Value {
 
 impl Eq for Value {}
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FunctionType {
     pub inputs: Vec<ArbValueType>,
     pub outputs: Vec<ArbValueType>,
@@ -232,6 +232,20 @@ impl<D: DataReader, E: EvmApi<D>> NativeInstance<D, E> {
         global.set(store, value.into()).map_err(ErrReport::msg)
     }
 
+    /// Reads a `u128` split across two `i64` globals, the first holding the low 64 bits and the
+    /// second the high 64 bits. Each half is reinterpreted as unsigned.
+    pub fn get_global_pair(&mut self, lo: &str, hi: &str) -> Result<u128> {
+        let lo: u64 = self.get_global(lo)?;
+        let hi: u64 = self.get_global(hi)?;
+        Ok((u128::from(hi) << 64) | u128::from(lo))
+    }
+
+    /// Writes a `u128` across two `i64` globals, the inverse of [`Self::get_global_pair`].
+    pub fn set_global_pair(&mut self, lo: &str, hi: &str, value: u128) -> Result<()> {
+        self.set_global(lo, value as u64)?;
+        self.set_global(hi, (value >> 64) as u64)
+    }
+
     pub fn call_func<R>(&mut self, func: TypedFunction<(), R>, ink: u64) -> Result<R>
     where
         R: WasmTypeList,
@@ -25,7 +25,11 @@ use eyre::{bail, ensure, Result};
 use prover::{
     binary,
     programs::{
+        config::{CompileMemoryParams, CompilePricingParams},
         counter::{Counter, CountingMachine},
+        dynamic::DynamicMeter,
+        limits::ModuleLimits,
+        meter::{self, Meter},
         prelude::*,
         start::StartMover,
         MiddlewareWrapper, ModuleMod,
@@ -34,7 +38,7 @@ use prover::{
 };
 use std::{collections::HashMap, path::Path, sync::Arc, time::Instant};
 use wasmer::wasmparser::Operator;
-use wasmer::{CompilerConfig, ExportIndex, Imports, Pages, Store};
+use wasmer::{CompilerConfig, ExportIndex, Imports, Module, Pages, Store};
 use wasmer_compiler_singlepass::Singlepass;
 
 #[test]
@@ -71,6 +75,166 @@ fn test_ink() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_ink_remaining() -> Result<()> {
+    let mut compile = test_compile_config();
+    compile.pricing.costs = super::expensive_add;
+
+    let mut native = TestInstance::new_test("tests/add.wat", compile)?;
+    let exports = &native.exports;
+    let add_one = exports.get_typed_function::<i32, i32>(&native.store, "add_one")?;
+
+    let (instance, store) = (&mut native.instance, &mut native.store);
+    meter::set_ink_remaining(instance, store, 150);
+    assert_eq!(meter::ink_remaining(instance, store), 150);
+    assert_eq!(add_one.call(store, 32)?, 33);
+    assert_eq!(meter::ink_status(instance, store), MachineMeter::Ready(50));
+
+    assert!(add_one.call(store, 32).is_err());
+    assert_eq!(meter::ink_status(instance, store), MachineMeter::Exhausted);
+
+    // refilling clears the status
+    meter::set_ink_remaining(instance, store, 100);
+    assert_eq!(meter::ink_status(instance, store), MachineMeter::Ready(100));
+    assert_eq!(native.ink_left(), MachineMeter::Ready(100));
+    Ok(())
+}
+
+#[test]
+fn test_conversion_ink() -> Result<()> {
+    let mut compile = test_compile_config();
+    compile.pricing.costs = |_, _| 10;
+    compile.pricing.conversion_ink = Some(1000);
+
+    let mut native = TestInstance::new_test("tests/conversion.wat", compile)?;
+    let exports = &native.exports;
+    let trunc = exports.get_typed_function::<f64, i32>(&native.store, "trunc")?;
+    let add_one = exports.get_typed_function::<i32, i32>(&native.store, "add_one")?;
+
+    // local.get + i32.trunc_f64_s + end
+    native.set_ink(10_000);
+    assert_eq!(trunc.call(&mut native.store, 32.5)?, 32);
+    assert_eq!(
+        native.ink_left(),
+        MachineMeter::Ready(10_000 - 10 - 1000 - 10)
+    );
+
+    // local.get + i32.const + i32.add + end
+    native.set_ink(10_000);
+    assert_eq!(add_one.call(&mut native.store, 32)?, 33);
+    assert_eq!(native.ink_left(), MachineMeter::Ready(10_000 - 4 * 10));
+    Ok(())
+}
+
+#[test]
+fn test_local_ink() -> Result<()> {
+    let mut compile = test_compile_config();
+    compile.pricing.costs = |_, _| 0;
+    compile.pricing.local_ink = 100;
+
+    let mut native = TestInstance::new_test("tests/locals.wat", compile)?;
+    let exports = &native.exports;
+    let few = exports.get_typed_function::<(), ()>(&native.store, "few")?;
+    let many = exports.get_typed_function::<(), ()>(&native.store, "many")?;
+
+    native.set_ink(10_000);
+    few.call(&mut native.store)?;
+    assert_eq!(native.ink_left(), MachineMeter::Ready(10_000 - 2 * 100));
+
+    native.set_ink(10_000);
+    many.call(&mut native.store)?;
+    assert_eq!(native.ink_left(), MachineMeter::Ready(10_000 - 10 * 100));
+
+    // the frame is paid for before any code runs
+    native.set_ink(999);
+    assert!(many.call(&mut native.store).is_err());
+    assert_eq!(native.ink_left(), MachineMeter::Exhausted);
+    Ok(())
+}
+
+#[test]
+fn test_branch_ink() -> Result<()> {
+    // the meter charges each basic block on entry, and since `if` and `else` end blocks,
+    // each arm is only paid for when executed, making refunds for the other arm unnecessary
+    let mut compile = test_compile_config();
+    compile.pricing.costs = super::expensive_add;
+
+    let mut native = TestInstance::new_test("tests/branches.wat", compile)?;
+    let exports = &native.exports;
+    let branch = exports.get_typed_function::<i32, i32>(&native.store, "branch")?;
+    let no_else = exports.get_typed_function::<i32, ()>(&native.store, "no_else")?;
+
+    macro_rules! charged {
+        ($func:expr, $cond:expr, $ink:expr) => {
+            native.set_ink(10_000);
+            $func.call(&mut native.store, $cond)?;
+            assert_eq!(native.ink_left(), MachineMeter::Ready(10_000 - $ink));
+        };
+    }
+
+    charged!(branch, 1, 200);
+    charged!(branch, 0, 0);
+    charged!(no_else, 1, 100);
+    charged!(no_else, 0, 0);
+    Ok(())
+}
+
+#[test]
+fn test_unsupported_bulk_ops() -> Result<()> {
+    // in memory-init.wat
+    //     the `init` function copies a passive data segment into memory, which the prover can't do
+    // in table-grow.wat
+    //     the `grow` function grows a table, which the prover can't do either
+    //
+    // native and prover builds must reject these operators alike
+
+    let pricing = CompilePricingParams::default();
+
+    for file in ["tests/memory-init.wat", "tests/table-grow.wat"] {
+        let mut compiler = Singlepass::new();
+        compiler.canonicalize_nans(true);
+        compiler.enable_verifier();
+        let meter = MiddlewareWrapper::new(Meter::new(&pricing));
+        let dygas = MiddlewareWrapper::new(DynamicMeter::new(&pricing));
+        compiler.push_middleware(Arc::new(meter));
+        compiler.push_middleware(Arc::new(dygas));
+
+        let store = Store::new(compiler);
+        assert!(TestInstance::new_from_store(file, store, Imports::new()).is_err());
+
+        let path = Path::new(file);
+        let wasm = wasmer::wat2wasm(&std::fs::read(path)?)?;
+        let prover = binary::parse(&wasm, path).and_then(|mut bin| {
+            bin.apply_middleware(&Meter::new(&pricing))?;
+            bin.apply_middleware(&DynamicMeter::new(&pricing))
+        });
+        assert!(prover.is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_import_ink() -> Result<()> {
+    let mut compile = test_compile_config();
+    compile.pricing.costs = |_, _| 0;
+    let noop = ("test".to_owned(), "noop".to_owned());
+    compile.pricing.import_ink.insert(noop, 500);
+
+    let mut native = TestInstance::new_test("tests/host-call.wat", compile)?;
+    let exports = &native.exports;
+    let call_host = exports.get_typed_function::<(), ()>(&native.store, "call_host")?;
+    let call_local = exports.get_typed_function::<(), ()>(&native.store, "call_local")?;
+
+    native.set_ink(10_000);
+    call_host.call(&mut native.store)?;
+    assert_eq!(native.ink_left(), MachineMeter::Ready(10_000 - 500));
+
+    native.set_ink(10_000);
+    call_local.call(&mut native.store)?;
+    assert_eq!(native.ink_left(), MachineMeter::Ready(10_000));
+    Ok(())
+}
+
 #[test]
 fn test_depth() -> Result<()> {
     // in depth.wat
@@ -113,6 +277,25 @@ fn test_depth() -> Result<()> {
     check(4 * frame_size + frame_size / 2, 4)
 }
 
+#[test]
+fn test_global_pair() -> Result<()> {
+    let mut native = TestInstance::new_test("tests/global-pair.wat", test_compile_config())?;
+
+    // the low half comes first
+    let value = native.get_global_pair("lo", "hi")?;
+    assert_eq!(value, (1 << 64) | u128::from(u64::MAX));
+
+    let value = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+    native.set_global_pair("lo", "hi", value)?;
+    assert_eq!(native.get_global_pair("lo", "hi")?, value);
+    assert_eq!(native.get_global::<u64>("lo")?, 0xfedc_ba98_7654_3210);
+    assert_eq!(native.get_global::<u64>("hi")?, 0x0123_4567_89ab_cdef);
+
+    assert!(native.get_global_pair("lo", "small").is_err());
+    assert!(native.get_global_pair("lo", "missing").is_err());
+    Ok(())
+}
+
 #[test]
 fn test_start() -> Result<()> {
     // in start.wat
@@ -143,6 +326,37 @@ fn test_start() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_start_ink() -> Result<()> {
+    // in start.wat
+    //     the `start` function performs a single add, which costs 100 ink when expensive
+    //     without a start mover, `start` runs at initialization on the ink it starts with
+
+    let instantiate = |start_ink| {
+        let mut pricing = CompilePricingParams::default();
+        pricing.costs = super::expensive_add;
+        pricing.start_ink = start_ink;
+
+        let mut compiler = Singlepass::new();
+        compiler.canonicalize_nans(true);
+        compiler.enable_verifier();
+        let meter = MiddlewareWrapper::new(Meter::new(&pricing));
+        compiler.push_middleware(Arc::new(meter));
+
+        let store = Store::new(compiler);
+        TestInstance::new_from_store("tests/start.wat", store, Imports::new())
+    };
+
+    assert!(instantiate(0).is_err());
+    assert!(instantiate(99).is_err());
+
+    let mut native = instantiate(150)?;
+    native.set_meter_data();
+    assert_eq!(native.get_global::<i32>("status")?, 11);
+    assert_eq!(native.ink_left(), MachineMeter::Ready(50));
+    Ok(())
+}
+
 #[test]
 fn test_count() -> Result<()> {
     let mut compiler = Singlepass::new();
@@ -234,6 +448,52 @@ fn test_module_mod() -> Result<()> {
     check("more")
 }
 
+#[test]
+fn test_module_limits() -> Result<()> {
+    // in module-limits.wat
+    //     there's 1 imported func, 2 defined funcs, 1 global, and 3 exports
+    //     there's an active data segment, which neither build counts, and a 7-byte passive one
+    //     native and prover builds must count these the same way so as to accept the same modules
+
+    let file = "tests/module-limits.wat";
+    let wasm = wasmer::wat2wasm(&std::fs::read(file)?)?;
+
+    let accepts = |bounds: CompileMemoryParams| -> Result<bool> {
+        let mut compiler = Singlepass::new();
+        let limits = MiddlewareWrapper::new(ModuleLimits::new(bounds));
+        compiler.push_middleware(Arc::new(limits));
+        let store = Store::new(compiler);
+        let native = Module::new(&store, &wasm).is_ok();
+
+        let mut bin = binary::parse(&wasm, Path::new(file))?;
+        let prover = bin.apply_middleware(&ModuleLimits::new(bounds)).is_ok();
+        ensure!(native == prover, "native and prover disagree on {bounds:?}");
+        Ok(native)
+    };
+
+    let mut bounds = CompileMemoryParams::default();
+    bounds.max_functions = Some(2);
+    bounds.max_imports = Some(1);
+    bounds.max_globals = Some(1);
+    bounds.max_exports = Some(3);
+    bounds.max_data_segments = Some(1);
+    bounds.max_data_bytes = Some(7);
+    assert!(accepts(bounds)?);
+
+    let tighter = |edit: fn(&mut CompileMemoryParams)| {
+        let mut bounds = bounds;
+        edit(&mut bounds);
+        accepts(bounds)
+    };
+    assert!(!tighter(|bounds| bounds.max_functions = Some(1))?);
+    assert!(!tighter(|bounds| bounds.max_imports = Some(0))?);
+    assert!(!tighter(|bounds| bounds.max_globals = Some(0))?);
+    assert!(!tighter(|bounds| bounds.max_exports = Some(2))?);
+    assert!(!tighter(|bounds| bounds.max_data_segments = Some(0))?);
+    assert!(!tighter(|bounds| bounds.max_data_bytes = Some(6))?);
+    Ok(())
+}
+
 #[test]
 fn test_heap() -> Result<()> {
     // in memory.wat
@@ -0,0 +1,28 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/nitro/blob/master/LICENSE
+
+(module
+    (memory 0 0)
+    (export "memory" (memory 0))
+    (func (export "branch") (param $cond i32) (result i32)
+        local.get $cond
+        (if (result i32)
+            (then
+                i32.const 1
+                i32.const 2
+                i32.add
+                i32.const 3
+                i32.add)
+            (else
+                i32.const 5)))
+    (func (export "no_else") (param $cond i32)
+        local.get $cond
+        (if
+            (then
+                i32.const 1
+                i32.const 2
+                i32.add
+                drop)))
+    (func (export "user_entrypoint") (param $args_len i32) (result i32)
+        (i32.const 0)
+    ))
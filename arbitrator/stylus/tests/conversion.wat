@@ -0,0 +1,16 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/nitro/blob/master/LICENSE
+
+(module
+    (memory 0 0)
+    (export "memory" (memory 0))
+    (func (export "trunc") (param $value f64) (result i32)
+        local.get $value
+        i32.trunc_f64_s)
+    (func (export "add_one") (param $value i32) (result i32)
+        local.get $value
+        i32.const 1
+        i32.add)
+    (func (export "user_entrypoint") (param $args_len i32) (result i32)
+        (i32.const 0)
+    ))
@@ -0,0 +1,12 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+(module
+    (memory 0 0)
+    (export "memory" (memory 0))
+    (global $lo (export "lo") (mut i64) (i64.const -1))
+    (global $hi (export "hi") (mut i64) (i64.const 1))
+    (global $small (export "small") (mut i32) (i32.const 0))
+    (func (export "user_entrypoint") (param $args_len i32) (result i32)
+        (i32.const 0)
+    ))
@@ -0,0 +1,15 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/nitro/blob/master/LICENSE
+
+(module
+    (import "test" "noop" (func $noop))
+    (memory 0 0)
+    (export "memory" (memory 0))
+    (func $local)
+    (func (export "call_host")
+        call $noop)
+    (func (export "call_local")
+        call $local)
+    (func (export "user_entrypoint") (param $args_len i32) (result i32)
+        (i32.const 0)
+    ))
@@ -0,0 +1,13 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/nitro/blob/master/LICENSE
+
+(module
+    (memory 0 0)
+    (export "memory" (memory 0))
+    (func (export "few")
+        (local i32 i32))
+    (func (export "many")
+        (local i32 i32 i32 i32 i64 i64 i64 i64 i32 i32))
+    (func (export "user_entrypoint") (param $args_len i32) (result i32)
+        (i32.const 0)
+    ))
@@ -0,0 +1,9 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+(module
+    (memory 1 1)
+    (data "passive")
+    (func (export "init") (param $size i32)
+        (memory.init 0 (i32.const 0) (i32.const 0) (local.get $size))
+    ))
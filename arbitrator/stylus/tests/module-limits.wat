@@ -0,0 +1,14 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+(module
+    (import "test" "noop" (func))
+    (global $global i32 (i32.const 0))
+    (memory 1 1)
+    (data (i32.const 0) "active")
+    (data "passive")
+    (func $first)
+    (func $second)
+    (export "first" (func $first))
+    (export "second" (func $second))
+    (export "global" (global $global)))
@@ -0,0 +1,8 @@
+;; Copyright 2024, Offchain Labs, Inc.
+;; For license information, see https://github.com/OffchainLabs/nitro/blob/master/LICENSE
+
+(module
+    (table 0 20 funcref)
+    (func (export "grow") (param $delta i32) (result i32)
+        (table.grow (ref.null func) (local.get $delta))
+    ))